@@ -0,0 +1,53 @@
+//! Benchmarks for the per-step work and the per-frame decay pass. Run with `cargo bench`.
+
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion};
+
+use art::decay::Decay;
+use art::TuringMachine;
+
+/// The binary's built in plane machine.
+const GENOME: &str = "3,6,2,2,3,2,4,0,0,1,0,2,1,2,1,1,0,1,2,3,2,3,0,2,1,0,2,5,3,2,5,2,2,4,1,1,5,0,2,4,3,0,4,0,0,1,1,2,1,3,2,1,0,2,2,0";
+
+fn machine(width: usize, height: usize) -> TuringMachine {
+    TuringMachine::from_string(GENOME, width, height).expect("the benchmark machine parses")
+}
+
+fn single_machine(c: &mut Criterion) {
+    c.bench_function("single machine 1M steps", |b| {
+        b.iter_batched_ref(|| machine(512, 512), |machine| machine.step(1_000_000), BatchSize::LargeInput)
+    });
+}
+
+/// Only one machine runs at a time, so this steps 10k independent machines on small maps. They
+/// are built once, outside the measurement, and carry on from one iteration to the next, so
+/// only stepping is timed.
+fn many_machines(c: &mut Criterion) {
+    let mut machines: Vec<_> = (0..10_000).map(|_| machine(32, 32)).collect();
+    c.bench_function("10k machines 100 steps", |b| {
+        b.iter(|| {
+            for machine in machines.iter_mut() {
+                machine.step(100);
+            }
+        })
+    });
+}
+
+fn decay_pass(c: &mut Criterion) {
+    for &size in &[512, 2048] {
+        c.bench_function(&format!("decay pass {}x{}", size, size), |b| {
+            b.iter_batched_ref(
+                || {
+                    let mut machine = machine(size, size);
+                    machine.map.iter_mut().enumerate().for_each(|(i, cell)| *cell = (i % 6) as u8);
+                    machine.itr_count = 1;
+                    machine
+                },
+                |machine| Decay::Linear { amount: 1, every: 1 }.apply(machine, 0),
+                BatchSize::LargeInput,
+            )
+        });
+    }
+}
+
+criterion_group!(benches, single_machine, many_machines, decay_pass);
+criterion_main!(benches);
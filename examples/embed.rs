@@ -0,0 +1,34 @@
+//! Renders 100 frames of a random machine to PNGs in `embed_frames/`, using only the
+//! `Simulation` API.
+
+use std::fs;
+
+use art::palette::Palette;
+use art::simulation::{Recipe, Simulation};
+use image::RgbaImage;
+
+fn main() {
+    let recipe = Recipe {
+        width: 256,
+        height: 256,
+        steps_per_frame: 2_000,
+        palette: Palette::Viridis,
+        ..Recipe::default()
+    };
+    let mut simulation = Simulation::new(recipe).expect("invalid recipe");
+
+    fs::create_dir_all("embed_frames").expect("could not create embed_frames");
+    for frame in 0..100 {
+        simulation.advance();
+        let machine = simulation.machine();
+        let (width, height) = (machine.width as u32, machine.height as u32);
+        let rgba = simulation.frame_rgba().to_vec();
+        RgbaImage::from_raw(width, height, rgba)
+            .expect("frame has the wrong size")
+            .save(format!("embed_frames/frame_{:03}.png", frame))
+            .expect("could not write frame");
+    }
+
+    let stats = simulation.stats();
+    println!("{} iterations", stats.iteration);
+}
@@ -0,0 +1,85 @@
+use crate::{Mode, TuringMachine};
+
+/// Largest radius the brush can be grown to.
+pub const MAX_RADIUS: usize = 64;
+
+/// Hand painting onto the map. A dab sets every cell within `radius` of its center to one
+/// symbol, wrapping around the edges. Coordinates are in cells of the machine's display, so a
+/// line machine is painted where its rotated space-time diagram shows on screen.
+pub struct Brush {
+    pub radius: usize,
+    /// Symbol painted by the left button. It is capped to the machine's symbols when painting,
+    /// so it stays valid when a machine with fewer symbols takes over.
+    pub value: u8,
+}
+
+impl Brush {
+    pub fn new() -> Brush {
+        Brush {
+            radius: 4,
+            value: u8::MAX,
+        }
+    }
+
+    pub fn resize(&mut self, grow: bool) {
+        self.radius = if grow {
+            (self.radius + 1).min(MAX_RADIUS)
+        } else {
+            self.radius.saturating_sub(1)
+        };
+    }
+
+    /// Steps the painted symbol up or down within the `num_symbols` of the current machine.
+    pub fn adjust_value(&mut self, up: bool, num_symbols: u16) {
+        let value = self.value.min(num_symbols as u8 - 1);
+        self.value = if up {
+            (value + 1).min(num_symbols as u8 - 1)
+        } else {
+            value.saturating_sub(1)
+        };
+    }
+
+    /// Paints `value` along the line from `from` to `to`, with a dab at least every cell so a
+    /// fast drag still leaves an unbroken stroke. Layer 0 is the only layer painted.
+    pub fn stroke(&self, machine: &mut TuringMachine, from: (f64, f64), to: (f64, f64), value: u8) {
+        let value = value.min(machine.num_symbols as u8 - 1);
+        let (dx, dy) = (to.0 - from.0, to.1 - from.1);
+        let dabs = dx.abs().max(dy.abs()).ceil() as usize;
+        for i in 0..=dabs {
+            let t = if dabs == 0 { 1.0 } else { i as f64 / dabs as f64 };
+            let x = (from.0 + dx * t).floor() as isize;
+            let y = (from.1 + dy * t).floor() as isize;
+            self.dab(machine, x, y, value);
+        }
+    }
+
+    fn dab(&self, machine: &mut TuringMachine, x: isize, y: isize, value: u8) {
+        let (width, height) = (machine.width as isize, machine.height as isize);
+        let r = self.radius as isize;
+        for dy in -r..=r {
+            for dx in -r..=r {
+                if dx * dx + dy * dy > r * r {
+                    continue;
+                }
+                let cx = (x + dx).rem_euclid(width) as usize;
+                let cy = map_row(machine, (y + dy).rem_euclid(height) as usize);
+                machine.map[machine.width * cy + cx] = value;
+            }
+        }
+    }
+}
+
+impl Default for Brush {
+    fn default() -> Brush {
+        Brush::new()
+    }
+}
+
+/// The map row drawn as display row `row`. A line machine's display starts right after its live
+/// row, see `TuringMachine::display`.
+fn map_row(machine: &TuringMachine, row: usize) -> usize {
+    match machine.mode {
+        Mode::Line => (machine.ypos + 1 + row) % machine.height,
+        Mode::Plane | Mode::Hex => row,
+    }
+}
@@ -0,0 +1,146 @@
+use std::path::PathBuf;
+use std::str::FromStr;
+
+/// Tunables that can be changed while the simulation runs.
+#[derive(Clone, Copy)]
+pub enum Parameter {
+    StepsPerFrame,
+    SparseBias,
+}
+
+/// Names accepted by `set`, in the order they are listed in the usage text.
+pub const PARAMETERS: &[(&str, Parameter)] = &[
+    ("steps_per_frame", Parameter::StepsPerFrame),
+    ("sparse_bias", Parameter::SparseBias),
+];
+
+/// Most steps per frame `set` accepts. Far more than any frame rate keeps up with, but a frame
+/// still ends in well under a second.
+pub const MAX_STEPS_PER_FRAME: u32 = 10_000_000;
+
+/// A parameter with a value of its own type, already checked to be in range.
+#[derive(Clone, Copy)]
+pub enum Setting {
+    StepsPerFrame(u32),
+    SparseBias(f64),
+}
+
+impl Parameter {
+    /// Reads `value` as a setting for this parameter.
+    pub fn parse(self, value: &str) -> Result<Setting, String> {
+        match self {
+            Parameter::StepsPerFrame => u32::from_str(value)
+                .ok()
+                .filter(|&steps| steps <= MAX_STEPS_PER_FRAME)
+                .map(Setting::StepsPerFrame)
+                .ok_or_else(|| {
+                    format!("steps_per_frame must be a whole number from 0 to {}, not {}", MAX_STEPS_PER_FRAME, value)
+                }),
+            Parameter::SparseBias => {
+                let bias = f64::from_str(value).map_err(|_| format!("not a number: {}", value))?;
+                if !(0.0..=1.0).contains(&bias) {
+                    return Err("sparse_bias must be between 0 and 1".to_string());
+                }
+                Ok(Setting::SparseBias(bias))
+            }
+        }
+    }
+}
+
+pub enum Command {
+    Pause,
+    Play,
+    Step(u32),
+    Reset,
+    Set(Setting),
+    /// Replaces the running machine with one parsed from a machine string.
+    Spawn(String),
+    /// Writes the machine and its map to a save file, see `save::write`.
+    Save(PathBuf),
+}
+
+pub fn parse(line: &str) -> Result<Command, String> {
+    let mut words = line.split_whitespace();
+    let command = match words.next() {
+        Some("pause") => Command::Pause,
+        Some("play") => Command::Play,
+        Some("reset") => Command::Reset,
+        Some("step") => {
+            let count = words.next().ok_or("step needs a count")?;
+            Command::Step(u32::from_str(count).map_err(|_| format!("not a step count: {}", count))?)
+        }
+        Some("set") => {
+            let name = words.next().ok_or("set needs a parameter name")?;
+            let parameter = PARAMETERS
+                .iter()
+                .find(|&&(n, _)| n == name)
+                .map(|&(_, p)| p)
+                .ok_or_else(|| format!("no such parameter: {}", name))?;
+            let value = words.next().ok_or("set needs a value")?;
+            Command::Set(parameter.parse(value)?)
+        }
+        Some("spawn") => Command::Spawn(words.next().ok_or("spawn needs a machine string")?.to_string()),
+        Some("save") => Command::Save(PathBuf::from(words.next().ok_or("save needs a path")?)),
+        Some(other) => return Err(format!("unknown command: {}", other)),
+        None => return Err("empty command".to_string()),
+    };
+
+    match words.next() {
+        Some(extra) => Err(format!("unexpected argument: {}", extra)),
+        None => Ok(command),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::path::Path;
+
+    #[test]
+    fn parses_every_command() {
+        assert!(matches!(parse("pause"), Ok(Command::Pause)));
+        assert!(matches!(parse(" play "), Ok(Command::Play)));
+        assert!(matches!(parse("reset"), Ok(Command::Reset)));
+        assert!(matches!(parse("step 100"), Ok(Command::Step(100))));
+        assert!(matches!(
+            parse("set steps_per_frame 800"),
+            Ok(Command::Set(Setting::StepsPerFrame(800)))
+        ));
+        assert!(matches!(
+            parse("set steps_per_frame 0"),
+            Ok(Command::Set(Setting::StepsPerFrame(0)))
+        ));
+        assert!(matches!(
+            parse("set sparse_bias 0.25"),
+            Ok(Command::Set(Setting::SparseBias(value))) if value == 0.25
+        ));
+        assert!(matches!(
+            parse("spawn v3:1,2,0,1,0,0,0,1"),
+            Ok(Command::Spawn(genome)) if genome == "v3:1,2,0,1,0,0,0,1"
+        ));
+        assert!(matches!(parse("save world.bin"), Ok(Command::Save(path)) if path == Path::new("world.bin")));
+    }
+
+    #[test]
+    fn rejects_malformed_commands() {
+        let error = |line| parse(line).err().expect("the line should not parse");
+        assert_eq!(error(""), "empty command");
+        assert_eq!(error("jump"), "unknown command: jump");
+        assert_eq!(error("step"), "step needs a count");
+        assert_eq!(error("step -1"), "not a step count: -1");
+        assert_eq!(error("set energy 1"), "no such parameter: energy");
+        assert_eq!(error("set sparse_bias"), "set needs a value");
+        assert_eq!(error("set sparse_bias much"), "not a number: much");
+        assert_eq!(error("set sparse_bias 1.5"), "sparse_bias must be between 0 and 1");
+        for steps in &["1e12", "-5", "2.7", "10000001", "many"] {
+            assert_eq!(
+                parse(&format!("set steps_per_frame {}", steps)).err().unwrap(),
+                format!("steps_per_frame must be a whole number from 0 to 10000000, not {}", steps)
+            );
+        }
+        assert_eq!(error("spawn"), "spawn needs a machine string");
+        assert_eq!(error("save"), "save needs a path");
+        assert_eq!(error("pause now"), "unexpected argument: now");
+    }
+}
@@ -0,0 +1,179 @@
+use crate::TuringMachine;
+
+/// Interval used by the presets the decay key cycles through.
+const PRESET_INTERVAL: u32 = 10_000;
+
+/// Fades the map over time so old trails give way to new ones. Decay never writes a symbol
+/// the machine doesn't have.
+#[derive(Clone, Copy, PartialEq)]
+pub enum Decay {
+    None,
+    /// Every `every` steps, lowers each cell by `amount`, stopping at 0.
+    Linear { amount: u8, every: u32 },
+    /// Every `every` steps, halves each cell.
+    Halve { every: u32 },
+    /// Once per frame, sets every cell below `below` to `to`, or to the machine's highest
+    /// symbol if `to` is out of its range.
+    Threshold { below: u8, to: u8 },
+}
+
+impl Decay {
+    /// Parses `none`, `linear:<amount>:<every>`, `halve:<every>` or `threshold:<below>:<to>`.
+    pub fn from_spec(spec: &str) -> Option<Decay> {
+        let mut parts = spec.split(':');
+        let kind = parts.next()?;
+        let mut number = || parts.next().and_then(|part| part.parse::<u32>().ok());
+        let decay = match kind {
+            "none" => Decay::None,
+            "linear" => Decay::Linear {
+                amount: number().filter(|&amount| (1..=255).contains(&amount))? as u8,
+                every: number().filter(|&every| every >= 1)?,
+            },
+            "halve" => Decay::Halve {
+                every: number().filter(|&every| every >= 1)?,
+            },
+            "threshold" => {
+                let below = number().filter(|&below| below <= 255)? as u8;
+                let to = number().filter(|&to| to < below as u32)? as u8;
+                Decay::Threshold { below, to }
+            }
+            _ => return None,
+        };
+        if parts.next().is_some() {
+            return None;
+        }
+        Some(decay)
+    }
+
+    pub fn next(self) -> Decay {
+        match self {
+            Decay::None => Decay::Linear {
+                amount: 1,
+                every: PRESET_INTERVAL,
+            },
+            Decay::Linear { .. } => Decay::Halve {
+                every: PRESET_INTERVAL,
+            },
+            Decay::Halve { .. } => Decay::Threshold { below: 2, to: 0 },
+            Decay::Threshold { .. } => Decay::None,
+        }
+    }
+
+    pub fn name(self) -> String {
+        match self {
+            Decay::None => "none".to_string(),
+            Decay::Linear { amount, every } => format!("linear:{}:{}", amount, every),
+            Decay::Halve { every } => format!("halve:{}", every),
+            Decay::Threshold { below, to } => format!("threshold:{}:{}", below, to),
+        }
+    }
+
+    /// Called once per frame with the iteration count the frame started at. Interval based
+    /// decays are applied once for every multiple of their interval the frame went past.
+    pub fn apply(self, machine: &mut TuringMachine, age: u32) {
+        let now = machine.itr_count;
+        let passed = |every: u32| (now / every).saturating_sub(age / every);
+        match self {
+            Decay::None => {}
+            Decay::Linear { amount, every } => {
+                let total = passed(every).saturating_mul(amount as u32).min(u8::MAX as u32) as u8;
+                if total > 0 {
+                    for cell in &mut machine.map {
+                        *cell = cell.saturating_sub(total);
+                    }
+                }
+            }
+            Decay::Halve { every } => {
+                let shift = passed(every);
+                if shift >= 8 {
+                    machine.map.fill(0);
+                } else if shift > 0 {
+                    // A plain u8 shift keeps the pass in bytes so it vectorizes.
+                    for cell in &mut machine.map {
+                        *cell >>= shift;
+                    }
+                }
+            }
+            Decay::Threshold { below, to } => {
+                let to = to.min(machine.num_symbols as u8 - 1);
+                for cell in &mut machine.map {
+                    if *cell < below {
+                        *cell = to;
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A four symbol machine at `itr_count` whose map holds `cells`.
+    fn machine(cells: &[u8], itr_count: u32) -> TuringMachine {
+        let mut machine = TuringMachine::from_string("v3:1,4,0,0,0,0,0,0,0,0,0,0,0,0", cells.len(), 2).unwrap();
+        machine.map[..cells.len()].copy_from_slice(cells);
+        machine.itr_count = itr_count;
+        machine
+    }
+
+    fn decayed(decay: Decay, cells: &[u8], age: u32, now: u32) -> Vec<u8> {
+        let mut machine = machine(cells, now);
+        decay.apply(&mut machine, age);
+        machine.map[..cells.len()].to_vec()
+    }
+
+    #[test]
+    fn none_leaves_the_map_alone() {
+        assert_eq!(decayed(Decay::None, &[0, 3, 200], 0, 1_000), [0, 3, 200]);
+    }
+
+    #[test]
+    fn linear_subtracts_per_interval_and_stops_at_zero() {
+        let decay = Decay::Linear { amount: 3, every: 10 };
+        // 5 to 25 passes 10 and 20.
+        assert_eq!(decayed(decay, &[0, 2, 6, 7, 255], 5, 25), [0, 0, 0, 1, 249]);
+        assert_eq!(decayed(decay, &[0, 2, 6, 7, 255], 11, 19), [0, 2, 6, 7, 255]);
+        // A total past 255 clears every cell instead of wrapping.
+        assert_eq!(decayed(decay, &[1, 255], 0, 1_000_000), [0, 0]);
+    }
+
+    #[test]
+    fn halve_shifts_per_interval() {
+        let decay = Decay::Halve { every: 10 };
+        assert_eq!(decayed(decay, &[1, 8, 200, 255], 0, 10), [0, 4, 100, 127]);
+        assert_eq!(decayed(decay, &[1, 8, 200, 255], 0, 30), [0, 1, 25, 31]);
+        assert_eq!(decayed(decay, &[1, 8, 200, 255], 0, 80), [0, 0, 0, 0]);
+        assert_eq!(decayed(decay, &[1, 8, 200, 255], 0, u32::MAX), [0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn threshold_lifts_low_cells() {
+        assert_eq!(
+            decayed(Decay::Threshold { below: 2, to: 1 }, &[0, 1, 2, 3], 0, 0),
+            [1, 1, 2, 3]
+        );
+        // Targets past the last symbol are clamped to it.
+        assert_eq!(
+            decayed(Decay::Threshold { below: 200, to: 100 }, &[0, 3, 200], 0, 0),
+            [3, 3, 200]
+        );
+    }
+
+    #[test]
+    fn parses_specs() {
+        assert!(Decay::from_spec("none") == Some(Decay::None));
+        assert!(Decay::from_spec("linear:2:50") == Some(Decay::Linear { amount: 2, every: 50 }));
+        assert!(Decay::from_spec("halve:7") == Some(Decay::Halve { every: 7 }));
+        assert!(Decay::from_spec("threshold:3:1") == Some(Decay::Threshold { below: 3, to: 1 }));
+        for bad in &["", "linear:0:5", "linear:2:0", "halve", "threshold:1:1", "none:1", "fade"] {
+            assert!(Decay::from_spec(bad).is_none(), "{}", bad);
+        }
+        let mut decay = Decay::None;
+        for _ in 0..4 {
+            assert!(Decay::from_spec(&decay.name()) == Some(decay));
+            decay = decay.next();
+        }
+    }
+}
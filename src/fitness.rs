@@ -0,0 +1,66 @@
+use crate::TuringMachine;
+
+/// Ways to score what a machine drew, higher being more interesting. All of them look only at
+/// the map, so any run can be scored after the fact.
+#[derive(Clone, Copy, PartialEq)]
+pub enum Fitness {
+    /// Cells that no longer hold the blank symbol.
+    Coverage,
+    /// Shannon entropy of the symbol histogram, in bits.
+    Entropy,
+    /// Share of the map inside the bounding box of the non-blank cells.
+    BoundingBox,
+}
+
+const FITNESSES: &[(&str, Fitness)] = &[
+    ("coverage", Fitness::Coverage),
+    ("entropy", Fitness::Entropy),
+    ("bbox", Fitness::BoundingBox),
+];
+
+impl Fitness {
+    pub fn from_name(name: &str) -> Option<Fitness> {
+        FITNESSES.iter().find(|&&(n, _)| n == name).map(|&(_, f)| f)
+    }
+
+    pub fn name(self) -> &'static str {
+        FITNESSES.iter().find(|&&(_, f)| f == self).unwrap().0
+    }
+
+    pub fn score(self, machine: &TuringMachine) -> f64 {
+        match self {
+            Fitness::Coverage => machine.map.iter().filter(|&&cell| cell != 0).count() as f64,
+            Fitness::Entropy => {
+                let mut counts = [0u64; 256];
+                for &cell in &machine.map {
+                    counts[cell as usize] += 1;
+                }
+                let cells = machine.map.len() as f64;
+                counts
+                    .iter()
+                    .filter(|&&count| count > 0)
+                    .map(|&count| {
+                        let p = count as f64 / cells;
+                        -p * p.log2()
+                    })
+                    .sum()
+            }
+            Fitness::BoundingBox => {
+                let (mut left, mut right) = (machine.width, 0);
+                let (mut top, mut bottom) = (machine.height, 0);
+                for (y, row) in machine.map.chunks_exact(machine.width).enumerate() {
+                    for (x, _) in row.iter().enumerate().filter(|&(_, &cell)| cell != 0) {
+                        left = left.min(x);
+                        right = right.max(x);
+                        top = top.min(y);
+                        bottom = bottom.max(y);
+                    }
+                }
+                if left > right {
+                    return 0.0;
+                }
+                ((right - left + 1) * (bottom - top + 1)) as f64 / machine.map.len() as f64
+            }
+        }
+    }
+}
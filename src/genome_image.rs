@@ -0,0 +1,57 @@
+use image::{Rgb, RgbImage};
+
+use crate::{Action, TuringMachine};
+
+/// Every rendered table is at least this many pixels on its shorter side.
+const MIN_SIDE: u32 = 256;
+
+fn action_color(action: &Action) -> [f32; 3] {
+    match action {
+        Action::Up => [1.0, 0.2, 0.2],
+        Action::Down => [0.2, 1.0, 0.2],
+        Action::Left => [0.2, 0.4, 1.0],
+        Action::Right => [1.0, 0.9, 0.2],
+        Action::UpLeft => [1.0, 0.2, 1.0],
+        Action::UpRight => [1.0, 0.6, 0.2],
+        Action::DownLeft => [0.2, 1.0, 1.0],
+        Action::DownRight => [0.6, 0.2, 1.0],
+        Action::SelfWrite => [1.0, 1.0, 1.0],
+        Action::SelfRead => [0.6, 0.6, 0.6],
+    }
+}
+
+/// Draws the transition table of `machine` as a grid with one row per state and one column per
+/// symbol. The hue of a cell says which action the transition takes and its brightness says
+/// which symbol it writes, from dim for symbol 0 up to full brightness for the last symbol.
+/// Sensing machines get one column per symbol and neighbourhood reading, with the readings of a
+/// symbol side by side. Cells are scaled up uniformly so small tables still come out at least
+/// 256px on a side.
+pub fn render(machine: &TuringMachine) -> RgbImage {
+    let rows = machine.num_states as u32;
+    let senses = machine.senses() as u32;
+    let cols = machine.num_symbols as u32 * senses;
+    let cell = MIN_SIDE.div_ceil(rows.min(cols));
+
+    let mut image = RgbImage::new(cols * cell, rows * cell);
+    for state in 0..rows {
+        for col in 0..cols {
+            let (symbol, sense) = (col / senses, col % senses);
+            let trans = machine.transition(state as u8, symbol as u8, sense as u8);
+            let brightness = (trans.symbol as f32 + 1.0) / machine.num_symbols as f32;
+            let color = action_color(&trans.action);
+            let pixel = Rgb([
+                (color[0] * brightness * 255.0) as u8,
+                (color[1] * brightness * 255.0) as u8,
+                (color[2] * brightness * 255.0) as u8,
+            ]);
+
+            for y in state * cell..(state + 1) * cell {
+                for x in col * cell..(col + 1) * cell {
+                    image.put_pixel(x, y, pixel);
+                }
+            }
+        }
+    }
+
+    image
+}
@@ -0,0 +1,114 @@
+use rand::rngs::SmallRng;
+use rand::{Rng, SeedableRng};
+
+use crate::{Action, TuringMachine};
+
+/// The map is summarized as a `GRID` x `GRID` grid of blocks, each holding the highest symbol
+/// inside it.
+const GRID: usize = 32;
+
+/// Signed distance from `from` to `to` along a wrapping axis of length `size`, taking whichever
+/// way around is shorter.
+pub(crate) fn toroidal_delta(from: usize, to: usize, size: usize) -> isize {
+    let forward = (to + size - from) % size;
+    if forward <= size / 2 {
+        forward as isize
+    } else {
+        forward as isize - size as isize
+    }
+}
+
+/// The move that takes the head one cell along `dx` and `dy`, or `None` if it is already there.
+fn toward(dx: isize, dy: isize) -> Option<Action> {
+    match (dx.signum(), dy.signum()) {
+        (0, 0) => None,
+        (1, 0) => Some(Action::Right),
+        (-1, 0) => Some(Action::Left),
+        (0, -1) => Some(Action::Up),
+        (0, 1) => Some(Action::Down),
+        (1, -1) => Some(Action::UpRight),
+        (-1, -1) => Some(Action::UpLeft),
+        (1, 1) => Some(Action::DownRight),
+        _ => Some(Action::DownLeft),
+    }
+}
+
+/// A soft pull toward the brightest part of the map. Every `interval` steps the machine's next
+/// move is replaced, with the given probability, by a step toward the center of the block with
+/// the highest symbol, going the short way around the torus. The block grid is rebuilt once
+/// per frame, which is a single pass over the map.
+pub struct Gravity {
+    probability: f64,
+    interval: u32,
+    maxima: [u8; GRID * GRID],
+    /// Cells per block along each axis, for the map the blocks were last built from.
+    block_width: usize,
+    block_height: usize,
+    width: usize,
+    height: usize,
+    /// Kept in rewind snapshots along with the run's own RNG.
+    pub(crate) rng: SmallRng,
+}
+
+impl Gravity {
+    /// The pulls are drawn from an RNG seeded with `seed`.
+    pub fn new(probability: f64, interval: u32, seed: u64) -> Gravity {
+        assert!((0.0..=1.0).contains(&probability), "gravity probability must be between 0 and 1");
+        assert!(interval >= 1, "gravity interval must be at least 1");
+
+        Gravity {
+            probability,
+            interval,
+            maxima: [0; GRID * GRID],
+            block_width: 1,
+            block_height: 1,
+            width: 1,
+            height: 1,
+            rng: SmallRng::seed_from_u64(seed),
+        }
+    }
+
+    pub fn refresh(&mut self, machine: &TuringMachine) {
+        self.width = machine.width;
+        self.height = machine.height;
+        self.block_width = machine.width.div_ceil(GRID);
+        self.block_height = machine.height.div_ceil(GRID);
+
+        self.maxima = [0; GRID * GRID];
+        for (y, row) in machine.map.chunks_exact(machine.width).enumerate() {
+            let blocks = &mut self.maxima[GRID * (y / self.block_height)..][..GRID];
+            for (block, cells) in blocks.iter_mut().zip(row.chunks(self.block_width)) {
+                *block = cells.iter().fold(*block, |max, &symbol| max.max(symbol));
+            }
+        }
+    }
+
+    /// Center of the first block holding the highest symbol, or `None` while the map is blank.
+    fn target(&self) -> Option<(usize, usize)> {
+        let (index, &max) = self
+            .maxima
+            .iter()
+            .enumerate()
+            .rev()
+            .max_by_key(|&(_, &max)| max)?;
+        if max == 0 {
+            return None;
+        }
+        let x = ((index % GRID) * self.block_width + self.block_width / 2).min(self.width - 1);
+        let y = ((index / GRID) * self.block_height + self.block_height / 2).min(self.height - 1);
+        Some((x, y))
+    }
+
+    /// Called before every step. Returns the move that should replace the machine's own move
+    /// on this step, if any.
+    pub fn pull(&mut self, machine: &TuringMachine) -> Option<Action> {
+        if !machine.itr_count.is_multiple_of(self.interval) || !self.rng.gen_bool(self.probability) {
+            return None;
+        }
+        let (x, y) = self.target()?;
+        toward(
+            toroidal_delta(machine.xpos, x, machine.width),
+            toroidal_delta(machine.ypos, y, machine.height),
+        )
+    }
+}
@@ -0,0 +1,111 @@
+use crate::screen::HEAD_MARKER;
+
+/// Window pixels per font pixel.
+const SCALE: usize = 2;
+/// Font pixels of padding around the text and between lines.
+const MARGIN: usize = 2;
+const GLYPH_WIDTH: usize = 5;
+const GLYPH_HEIGHT: usize = 7;
+
+/// A 5x7 bitmap font, one byte per row with the leftmost pixel in bit 4. Letters only come in
+/// upper case; lower case text is drawn with them.
+fn glyph(c: char) -> [u8; GLYPH_HEIGHT] {
+    match c.to_ascii_uppercase() {
+        '0' => [0x0e, 0x11, 0x13, 0x15, 0x19, 0x11, 0x0e],
+        '1' => [0x04, 0x0c, 0x04, 0x04, 0x04, 0x04, 0x0e],
+        '2' => [0x0e, 0x11, 0x01, 0x02, 0x04, 0x08, 0x1f],
+        '3' => [0x1f, 0x02, 0x04, 0x02, 0x01, 0x11, 0x0e],
+        '4' => [0x02, 0x06, 0x0a, 0x12, 0x1f, 0x02, 0x02],
+        '5' => [0x1f, 0x10, 0x1e, 0x01, 0x01, 0x11, 0x0e],
+        '6' => [0x06, 0x08, 0x10, 0x1e, 0x11, 0x11, 0x0e],
+        '7' => [0x1f, 0x01, 0x02, 0x04, 0x08, 0x08, 0x08],
+        '8' => [0x0e, 0x11, 0x11, 0x0e, 0x11, 0x11, 0x0e],
+        '9' => [0x0e, 0x11, 0x11, 0x0f, 0x01, 0x02, 0x0c],
+        'A' => [0x0e, 0x11, 0x11, 0x11, 0x1f, 0x11, 0x11],
+        'B' => [0x1e, 0x11, 0x11, 0x1e, 0x11, 0x11, 0x1e],
+        'C' => [0x0e, 0x11, 0x10, 0x10, 0x10, 0x11, 0x0e],
+        'D' => [0x1c, 0x12, 0x11, 0x11, 0x11, 0x12, 0x1c],
+        'E' => [0x1f, 0x10, 0x10, 0x1e, 0x10, 0x10, 0x1f],
+        'F' => [0x1f, 0x10, 0x10, 0x1e, 0x10, 0x10, 0x10],
+        'G' => [0x0e, 0x11, 0x10, 0x17, 0x11, 0x11, 0x0f],
+        'H' => [0x11, 0x11, 0x11, 0x1f, 0x11, 0x11, 0x11],
+        'I' => [0x0e, 0x04, 0x04, 0x04, 0x04, 0x04, 0x0e],
+        'J' => [0x07, 0x02, 0x02, 0x02, 0x02, 0x12, 0x0c],
+        'K' => [0x11, 0x12, 0x14, 0x18, 0x14, 0x12, 0x11],
+        'L' => [0x10, 0x10, 0x10, 0x10, 0x10, 0x10, 0x1f],
+        'M' => [0x11, 0x1b, 0x15, 0x15, 0x11, 0x11, 0x11],
+        'N' => [0x11, 0x11, 0x19, 0x15, 0x13, 0x11, 0x11],
+        'O' => [0x0e, 0x11, 0x11, 0x11, 0x11, 0x11, 0x0e],
+        'P' => [0x1e, 0x11, 0x11, 0x1e, 0x10, 0x10, 0x10],
+        'Q' => [0x0e, 0x11, 0x11, 0x11, 0x15, 0x12, 0x0d],
+        'R' => [0x1e, 0x11, 0x11, 0x1e, 0x14, 0x12, 0x11],
+        'S' => [0x0f, 0x10, 0x10, 0x0e, 0x01, 0x01, 0x1e],
+        'T' => [0x1f, 0x04, 0x04, 0x04, 0x04, 0x04, 0x04],
+        'U' => [0x11, 0x11, 0x11, 0x11, 0x11, 0x11, 0x0e],
+        'V' => [0x11, 0x11, 0x11, 0x11, 0x11, 0x0a, 0x04],
+        'W' => [0x11, 0x11, 0x11, 0x15, 0x15, 0x15, 0x0a],
+        'X' => [0x11, 0x11, 0x0a, 0x04, 0x0a, 0x11, 0x11],
+        'Y' => [0x11, 0x11, 0x11, 0x0a, 0x04, 0x04, 0x04],
+        'Z' => [0x1f, 0x01, 0x02, 0x04, 0x08, 0x10, 0x1f],
+        '.' => [0x00, 0x00, 0x00, 0x00, 0x00, 0x0c, 0x0c],
+        ':' => [0x00, 0x0c, 0x0c, 0x00, 0x0c, 0x0c, 0x00],
+        '-' => [0x00, 0x00, 0x00, 0x1f, 0x00, 0x00, 0x00],
+        '/' => [0x00, 0x01, 0x02, 0x04, 0x08, 0x10, 0x00],
+        ' ' => [0x00; GLYPH_HEIGHT],
+        _ => [0x0e, 0x11, 0x01, 0x02, 0x04, 0x00, 0x04],
+    }
+}
+
+/// Draws `lines` into the top left corner of a `width` x `height` window buffer with
+/// `channels` bytes per pixel. The text gets `HEAD_MARKER`, which the shader always draws white,
+/// on a box of `paper`, which should be a dark symbol so the text stays readable whatever the
+/// map looks like underneath. Row 0 of the buffer is the bottom of the window. Text that doesn't
+/// fit is cut off.
+pub fn draw(pixels: &mut [u8], channels: usize, width: usize, height: usize, lines: &[String], paper: u8) {
+    if lines.is_empty() {
+        return;
+    }
+    let columns = lines.iter().map(|line| line.chars().count()).max().unwrap();
+    let box_width = MARGIN + columns * (GLYPH_WIDTH + 1) - 1 + MARGIN;
+    let box_height = MARGIN + lines.len() * (GLYPH_HEIGHT + MARGIN);
+
+    // Font pixel `x`, `y` counted from the top left of the box.
+    let mut set = |x: usize, y: usize, value: u8| {
+        for sy in 0..SCALE {
+            let down = y * SCALE + sy;
+            if down >= height {
+                return;
+            }
+            let row = width * (height - 1 - down);
+            for sx in 0..SCALE {
+                let across = x * SCALE + sx;
+                if across < width {
+                    pixels[channels * (row + across)] = value;
+                    if channels == 2 {
+                        // Layer 1 would otherwise shade the text.
+                        pixels[channels * (row + across) + 1] = 0;
+                    }
+                }
+            }
+        }
+    };
+
+    for y in 0..box_height {
+        for x in 0..box_width {
+            set(x, y, paper);
+        }
+    }
+    for (n, line) in lines.iter().enumerate() {
+        let top = MARGIN + n * (GLYPH_HEIGHT + MARGIN);
+        for (column, c) in line.chars().enumerate() {
+            let left = MARGIN + column * (GLYPH_WIDTH + 1);
+            for (dy, bits) in glyph(c).iter().enumerate() {
+                for dx in 0..GLYPH_WIDTH {
+                    if (bits >> (GLYPH_WIDTH - 1 - dx)) & 1 == 1 {
+                        set(left + dx, top + dy, HEAD_MARKER);
+                    }
+                }
+            }
+        }
+    }
+}
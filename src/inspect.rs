@@ -0,0 +1,100 @@
+use std::fmt::Write;
+use std::path::{Path, PathBuf};
+
+use crate::genome_image;
+use crate::gravity::toroidal_delta;
+use crate::{Mode, TuringMachine};
+
+/// Cells from the head within which a click picks the machine.
+pub const PICK_RADIUS: usize = 8;
+
+/// Whether display cell `x`, `y` is within `PICK_RADIUS` cells of the head each way, going the
+/// short way around the edges. A line machine's head is on the last row of its display.
+pub fn picks(machine: &TuringMachine, x: usize, y: usize) -> bool {
+    let head_y = match machine.mode {
+        Mode::Line => machine.height - 1,
+        Mode::Plane | Mode::Hex => machine.ypos,
+    };
+    toroidal_delta(machine.xpos, x, machine.width).unsigned_abs() <= PICK_RADIUS
+        && toroidal_delta(head_y, y, machine.height).unsigned_abs() <= PICK_RADIUS
+}
+
+/// For every state, whether the machine can get there from state 0. Any symbol may turn up
+/// under the head, so every entry of a reachable state counts as taken. Self-modification only
+/// ever rewrites symbols, so it can't change the answer.
+pub fn reachable_states(machine: &TuringMachine) -> Vec<bool> {
+    let mut reachable = vec![false; machine.num_states as usize];
+    reachable[0] = true;
+    let mut pending = vec![0u8];
+    while let Some(state) = pending.pop() {
+        for symbol in 0..machine.num_symbols as u8 {
+            for sense in 0..machine.senses() {
+                let next = machine.transition(state, symbol, sense).state;
+                if !reachable[next as usize] {
+                    reachable[next as usize] = true;
+                    pending.push(next);
+                }
+            }
+        }
+    }
+    reachable
+}
+
+/// A readable dump of the machine: where it is and how old it is, then one line per state and
+/// symbol with the state it moves to, the symbol it writes and the move it makes. Sensing
+/// machines get a line per neighbourhood reading too, tagged `n<count>`. Entries of states that
+/// can't be reached from state 0 are marked unreachable.
+pub fn report(machine: &TuringMachine) -> String {
+    let reachable = reachable_states(machine);
+    let mut report = String::new();
+    writeln!(
+        report,
+        "machine {}x{} state {} x {} y {} age {}",
+        machine.num_states, machine.num_symbols, machine.state, machine.xpos, machine.ypos, machine.itr_count
+    )
+    .unwrap();
+    for state in 0..machine.num_states as u8 {
+        for (symbol, sense) in (0..machine.num_symbols as u8)
+            .flat_map(|symbol| (0..machine.senses()).map(move |sense| (symbol, sense)))
+        {
+            let trans = machine.transition(state, symbol, sense);
+            let action = if machine.version < 2 {
+                trans.action.mirror_horizontal()
+            } else {
+                trans.action
+            };
+            let neighbours = if machine.is_sensing() {
+                format!(" n{}", sense)
+            } else {
+                String::new()
+            };
+            let mut line = format!(
+                "  {:>3} {:>3}{} -> state {:>3} write {:>3} {:<9}",
+                state,
+                symbol,
+                neighbours,
+                trans.state,
+                trans.symbol,
+                action.name()
+            );
+            if !machine.layers.is_empty() {
+                write!(line, " read layer {} write layer {}", trans.read_layer, trans.write_layer).unwrap();
+            }
+            if !reachable[state as usize] {
+                line += " unreachable";
+            }
+            report += line.trim_end();
+            report.push('\n');
+        }
+    }
+    report
+}
+
+/// Saves the `genome_image` thumbnail of the machine's table into `dir` to go with `report`, and
+/// returns where it went. The file is named after the genome hash, so inspecting the same table
+/// again writes over the same file.
+pub fn save_thumbnail(machine: &TuringMachine, dir: &Path) -> image::ImageResult<PathBuf> {
+    let path = dir.join(format!("genome-{:016x}.png", machine.genome_hash()));
+    genome_image::render(machine).save(&path)?;
+    Ok(path)
+}
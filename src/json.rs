@@ -0,0 +1,231 @@
+use serde::{Deserialize, Serialize};
+
+use crate::{check_layers, check_sensing, check_table_size};
+use crate::{Mode, TuringMachine, ACTIONS, FORMAT_VERSION, SENSES};
+
+/// A machine definition as it appears in JSON. `table` is listed state by state, each state
+/// with an entry for every symbol, and its actions are the moves the machine actually makes,
+/// whatever format version the machine was first written in. Sensing machines list `SENSES`
+/// entries per symbol, for 0 to 3 nonzero neighbours. Layers are only written for machines that
+/// have more than one.
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Definition {
+    num_states: u16,
+    num_symbols: u16,
+    #[serde(default = "plane", skip_serializing_if = "is_plane")]
+    mode: String,
+    #[serde(default = "one", skip_serializing_if = "is_one")]
+    layers: u8,
+    #[serde(default, skip_serializing_if = "is_false")]
+    sensing: bool,
+    table: Vec<Entry>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Entry {
+    state: u8,
+    symbol: u8,
+    action: String,
+    #[serde(default, skip_serializing_if = "is_zero")]
+    read_layer: u8,
+    #[serde(default, skip_serializing_if = "is_zero")]
+    write_layer: u8,
+}
+
+fn plane() -> String {
+    "plane".to_string()
+}
+
+fn is_plane(mode: &str) -> bool {
+    mode == "plane"
+}
+
+fn one() -> u8 {
+    1
+}
+
+fn is_one(layers: &u8) -> bool {
+    *layers == 1
+}
+
+fn is_zero(layer: &u8) -> bool {
+    *layer == 0
+}
+
+fn is_false(flag: &bool) -> bool {
+    !*flag
+}
+
+impl TuringMachine {
+    /// Parses a machine definition written by `to_json`. The definition is turned into a machine
+    /// string, with one entry of the string for each entry of `table`, so most problems are
+    /// found by `from_string`. Problems with a single entry are reported as `table[i]`, with
+    /// its index in the JSON. Tables don't carry a symmetry tag.
+    pub fn from_json(json: &str, width: usize, height: usize) -> Result<TuringMachine, String> {
+        let definition: Definition = serde_json::from_str(json).map_err(|error| error.to_string())?;
+        let (prefix, mode) = match definition.mode.as_str() {
+            "plane" => ("", Mode::Plane),
+            "line" => ("1d:", Mode::Line),
+            "hex" => ("hex:", Mode::Hex),
+            other => return Err(format!("unknown mode \"{}\", expected plane, line or hex", other)),
+        };
+        let layers = definition.layers;
+
+        // from_string counts the numbers in the string, so the length is checked in entries here.
+        check_table_size(definition.num_states, definition.num_symbols)?;
+        check_layers(layers, mode)?;
+        check_sensing(definition.sensing, mode)?;
+        let senses = if definition.sensing { SENSES as usize } else { 1 };
+        let expected = definition.num_states as usize * definition.num_symbols as usize * senses;
+        if definition.table.len() != expected {
+            return Err(format!(
+                "table has {} entries, expected {}, one per state and symbol{}",
+                definition.table.len(),
+                expected,
+                if definition.sensing { " and neighbour count" } else { "" }
+            ));
+        }
+
+        let mut genome = format!("v{}:{}", FORMAT_VERSION, prefix);
+        if definition.sensing {
+            genome += "sense:";
+        }
+        if layers != 1 {
+            genome += &format!("layers{}:", layers);
+        }
+        genome += &format!("{},{}", definition.num_states, definition.num_symbols);
+        for (i, entry) in definition.table.iter().enumerate() {
+            let action = ACTIONS
+                .iter()
+                .find(|action| action.name() == entry.action)
+                .ok_or_else(|| format!("table[{}]: unknown action \"{}\"", i, entry.action))?;
+            // Single layer strings have no room for layers, so from_string can't catch these.
+            if layers == 1 && (entry.read_layer != 0 || entry.write_layer != 0) {
+                return Err(format!("table[{}]: layer is out of range", i));
+            }
+            genome += &format!(",{},{},{}", entry.state, entry.symbol, action.code());
+            if layers > 1 {
+                genome += &format!(",{},{}", entry.read_layer, entry.write_layer);
+            }
+        }
+
+        TuringMachine::from_string(&genome, width, height).map_err(|error| match error.entry_problem() {
+            Some((index, problem)) => format!("table[{}]: {}", index, problem),
+            None => error.to_string(),
+        })
+    }
+
+    /// Writes the machine's table as JSON that `from_json` reads back into the same machine.
+    /// Tables from before format version 3 are reordered, so a self-modifying one may reach
+    /// different entries as it wanders.
+    pub fn to_json(&self) -> String {
+        let mut table = Vec::with_capacity(self.table.len());
+        for state in 0..self.num_states as u8 {
+            for symbol in 0..self.num_symbols as u8 {
+                for sense in 0..self.senses() {
+                    let trans = self.transition(state, symbol, sense);
+                    let action = if self.version < 2 {
+                        trans.action.mirror_horizontal()
+                    } else {
+                        trans.action
+                    };
+                    table.push(Entry {
+                        state: trans.state,
+                        symbol: trans.symbol,
+                        action: action.name().to_string(),
+                        read_layer: trans.read_layer,
+                        write_layer: trans.write_layer,
+                    });
+                }
+            }
+        }
+        let mode = match self.mode {
+            Mode::Plane => "plane",
+            Mode::Line => "line",
+            Mode::Hex => "hex",
+        };
+        let definition = Definition {
+            num_states: self.num_states,
+            num_symbols: self.num_symbols,
+            mode: mode.to_string(),
+            layers: self.num_layers(),
+            sensing: self.sensing,
+            table,
+        };
+        serde_json::to_string_pretty(&definition).expect("machine definitions always serialize")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trips_through_json() {
+        let machine = TuringMachine::from_string("v3:layers2:1,2,0,1,4,1,0,0,0,7,0,1", 8, 8).unwrap();
+        let json = machine.to_json();
+        let parsed = TuringMachine::from_json(&json, 8, 8).unwrap();
+        assert_eq!(parsed.to_string(), machine.to_string());
+        assert_eq!(parsed.to_json(), json);
+    }
+
+    #[test]
+    fn reports_table_problems_through_from_string() {
+        let json = r#"{"num_states": 1, "num_symbols": 2, "table": [
+            {"state": 0, "symbol": 1, "action": "Left"},
+            {"state": 3, "symbol": 0, "action": "Up"}
+        ]}"#;
+        assert_eq!(
+            TuringMachine::from_json(json, 8, 8).err().unwrap(),
+            "table[1]: state 3 is out of range"
+        );
+        let json = r#"{"num_states": 1, "num_symbols": 2, "mode": "hex", "sensing": true, "table": []}"#;
+        assert_eq!(
+            TuringMachine::from_json(json, 8, 8).err().unwrap(),
+            "only square plane machines can sense their neighbours"
+        );
+    }
+
+    /// A definition of a 1 state, 2 symbol machine with the given header fields and `entries`,
+    /// all of them moving left but `bad`, which is spliced in at `at`.
+    fn definition(header: &str, entries: usize, at: usize, bad: &str) -> String {
+        let table: Vec<&str> = (0..entries)
+            .map(|i| if i == at { bad } else { r#"{"state": 0, "symbol": 0, "action": "Left"}"# })
+            .collect();
+        format!(r#"{{"num_states": 1, "num_symbols": 2, {} "table": [{}]}}"#, header, table.join(", "))
+    }
+
+    #[test]
+    fn reports_the_json_index_of_bad_entries() {
+        let bad_layer = r#"{"state": 0, "symbol": 0, "action": "Left", "write_layer": 2}"#;
+        assert_eq!(
+            TuringMachine::from_json(&definition(r#""layers": 2,"#, 2, 1, bad_layer), 8, 8).err().unwrap(),
+            "table[1]: layer 2 is out of range"
+        );
+        let bad_symbol = r#"{"state": 0, "symbol": 5, "action": "Up"}"#;
+        assert_eq!(
+            TuringMachine::from_json(&definition(r#""sensing": true,"#, 8, 6, bad_symbol), 8, 8).err().unwrap(),
+            "table[6]: symbol 5 is out of range"
+        );
+        let bad_move = r#"{"state": 0, "symbol": 0, "action": "Down"}"#;
+        assert_eq!(
+            TuringMachine::from_json(&definition(r#""mode": "hex","#, 2, 0, bad_move), 8, 8).err().unwrap(),
+            "table[0]: hex machines can't move straight up or down"
+        );
+    }
+
+    #[test]
+    fn counts_the_table_in_entries() {
+        let left = r#"{"state": 0, "symbol": 0, "action": "Left"}"#;
+        assert_eq!(
+            TuringMachine::from_json(&definition(r#""layers": 2,"#, 3, 0, left), 8, 8).err().unwrap(),
+            "table has 3 entries, expected 2, one per state and symbol"
+        );
+        assert_eq!(
+            TuringMachine::from_json(&definition(r#""sensing": true,"#, 2, 0, left), 8, 8).err().unwrap(),
+            "table has 2 entries, expected 8, one per state and symbol and neighbour count"
+        );
+    }
+}
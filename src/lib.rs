@@ -0,0 +1,1596 @@
+//! The Turing machines behind `art`, independent of any window. The binary drives a machine
+//! from here and puts its map on screen; everything in this crate runs headless.
+
+use rand::{
+    distributions::{Distribution, Standard},
+    Rng,
+};
+
+use std::fmt;
+use std::str::FromStr;
+
+pub mod brush;
+pub mod command;
+pub mod decay;
+pub mod fitness;
+pub mod genome_image;
+pub mod gravity;
+pub mod hud;
+pub mod inspect;
+pub mod json;
+pub mod metadata;
+pub mod palette;
+pub mod paths;
+pub mod record;
+pub mod rewind;
+pub mod save;
+pub mod screen;
+pub mod shader;
+pub mod signature;
+pub mod simulation;
+pub mod splat;
+pub mod trace;
+
+/// Version of the machine string format written by `Display`. Older strings are still replayed
+/// the way they were written, so they render as they always did:
+/// - 1 (no `v<n>:` prefix): Left and Right, and the horizontal half of the diagonals, are
+///   swapped.
+/// - 2: the table is listed symbol by symbol, each symbol with an entry for every state.
+/// - 3: the table is listed state by state, each state with an entry for every symbol.
+pub const FORMAT_VERSION: u8 = 3;
+
+/// Map size used unless another one is asked for.
+pub const DEFAULT_WIDTH: usize = 1024;
+pub const DEFAULT_HEIGHT: usize = 1025;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Action {
+    Up,
+    Down,
+    Left,
+    Right,
+    UpLeft,
+    UpRight,
+    DownLeft,
+    DownRight,
+    /// Overwrites the written symbol of one of the machine's own table entries with the symbol
+    /// under the head.
+    SelfWrite,
+    /// Writes the symbol stored in one of the machine's own table entries onto the map.
+    SelfRead,
+}
+
+impl Action {
+    pub fn name(self) -> &'static str {
+        match self {
+            Action::Up => "Up",
+            Action::Down => "Down",
+            Action::Left => "Left",
+            Action::Right => "Right",
+            Action::UpLeft => "UpLeft",
+            Action::UpRight => "UpRight",
+            Action::DownLeft => "DownLeft",
+            Action::DownRight => "DownRight",
+            Action::SelfWrite => "SelfWrite",
+            Action::SelfRead => "SelfRead",
+        }
+    }
+
+    /// The number standing for this action in machine strings.
+    fn code(self) -> u8 {
+        match self {
+            Action::Left => 0,
+            Action::Right => 1,
+            Action::Up => 2,
+            Action::Down => 3,
+            Action::UpLeft => 4,
+            Action::UpRight => 5,
+            Action::DownLeft => 6,
+            Action::DownRight => 7,
+            Action::SelfWrite => 8,
+            Action::SelfRead => 9,
+        }
+    }
+
+    /// The same move reflected left to right.
+    fn mirror_horizontal(self) -> Action {
+        match self {
+            Action::Left => Action::Right,
+            Action::Right => Action::Left,
+            Action::UpLeft => Action::UpRight,
+            Action::UpRight => Action::UpLeft,
+            Action::DownLeft => Action::DownRight,
+            Action::DownRight => Action::DownLeft,
+            other => other,
+        }
+    }
+
+    /// The same move reflected top to bottom.
+    fn mirror_vertical(self) -> Action {
+        match self {
+            Action::Up => Action::Down,
+            Action::Down => Action::Up,
+            Action::UpLeft => Action::DownLeft,
+            Action::DownLeft => Action::UpLeft,
+            Action::UpRight => Action::DownRight,
+            Action::DownRight => Action::UpRight,
+            other => other,
+        }
+    }
+}
+
+/// Every action, in declaration order so `ACTIONS[action as usize] == action`.
+pub const ACTIONS: [Action; 10] = [
+    Action::Up,
+    Action::Down,
+    Action::Left,
+    Action::Right,
+    Action::UpLeft,
+    Action::UpRight,
+    Action::DownLeft,
+    Action::DownRight,
+    Action::SelfWrite,
+    Action::SelfRead,
+];
+
+/// How far each action moves the head of a plane machine along x and y, indexed by
+/// `action as usize`. The self-modifying actions stay put.
+const MOVES: [(isize, isize); ACTIONS.len()] = [
+    (0, -1),
+    (0, 1),
+    (-1, 0),
+    (1, 0),
+    (-1, -1),
+    (1, -1),
+    (-1, 1),
+    (1, 1),
+    (0, 0),
+    (0, 0),
+];
+
+/// The six moves available on a hex grid. There is no straight vertical neighbour, so the
+/// diagonals take the place of Up and Down.
+pub const HEX_ACTIONS: [Action; 6] = [
+    Action::Left,
+    Action::Right,
+    Action::UpLeft,
+    Action::UpRight,
+    Action::DownLeft,
+    Action::DownRight,
+];
+
+/// Any of the eight moves on the square plane, uniformly.
+impl Distribution<Action> for Standard {
+    fn sample<R: Rng + ?Sized>(&self, rng: &mut R) -> Action {
+        ACTIONS[rng.gen_range(0, 8)]
+    }
+}
+
+/// The tape a machine works on. `Plane` machines roam the whole map. `Line` machines are classic
+/// one dimensional machines confined to a single row, and the map holds the history of that row
+/// as a ring of rows so it can be shown as a space-time diagram. `Hex` machines roam the map as
+/// a hex grid, addressed with every odd row offset half a cell towards +x.
+#[derive(Clone, Copy, PartialEq)]
+pub enum Mode {
+    Plane,
+    Line,
+    Hex,
+}
+
+#[derive(Clone)]
+pub struct Transition {
+    state: u8,
+    symbol: u8,
+    action: Action,
+    /// Layer the next step reads from.
+    read_layer: u8,
+    /// Layer `symbol` is written to.
+    write_layer: u8,
+    /// Index of the first table entry of `state`, worked out when the table is built so a step
+    /// gets from one entry to the next without a multiply.
+    start: u32,
+}
+
+/// Most byte planes a machine can work on.
+pub const MAX_LAYERS: u8 = 4;
+
+/// Neighbourhood readings a sensing machine tells apart: how many of the four cells next to the
+/// head are nonzero, with three or four both reading as 3.
+pub const SENSES: u8 = 4;
+
+pub struct TuringMachine {
+    /// One entry per state and symbol, times `SENSES` for sensing machines, in the order given
+    /// by `entry_index`.
+    table: Vec<Transition>,
+    /// Row-major cells, `width` per row. This is layer 0, the one that is shown in color.
+    pub map: Vec<u8>,
+    /// Further planes of cells laid out like `map`, layer 1 first. Empty unless the machine was
+    /// made with more than one layer, in which case each transition picks the layer it writes
+    /// to and the layer the next step reads from.
+    pub layers: Vec<Vec<u8>>,
+    pub width: usize,
+    pub height: usize,
+    pub num_states: u16,
+    pub num_symbols: u16,
+    pub mode: Mode,
+    /// Whether the table is also indexed by the neighbourhood of the head, see `SENSES`.
+    sensing: bool,
+    /// Symmetry the table was generated or loaded with, kept so it is written back out.
+    symmetry: TableSymmetry,
+    /// Machine string format the table was written for, which decides how its moves are read.
+    version: u8,
+    /// Distance in the table between the entries of neighbouring states and of neighbouring
+    /// symbols, worked out once from the layout so a lookup is two multiply-adds.
+    strides: (usize, usize),
+    pub state: u8,
+    /// Layer the next step reads from.
+    pub layer: u8,
+    pub xpos: usize,
+    pub ypos: usize,
+    pub itr_count: u32,
+    /// Number of SelfWrite and SelfRead actions executed so far.
+    pub self_modifications: u32,
+    /// Executed actions per variant, indexed by `action as usize`. Cleared by whoever reports
+    /// them.
+    pub action_counts: [u32; ACTIONS.len()],
+    /// Move that replaces the table's move on the next step, if that step moves at all.
+    pub pull: Option<Action>,
+}
+
+/// Mirror constraint for random tables. Table entries are paired up from both ends (entry `i`
+/// with entry `len - 1 - i`), and under a symmetry each entry's partner takes the mirrored
+/// move, so every move along a mirrored axis is balanced by one in the opposite direction.
+#[derive(Clone, Copy, PartialEq)]
+pub enum TableSymmetry {
+    None,
+    H,
+    V,
+    HV,
+}
+
+impl TableSymmetry {
+    /// Tag written in front of machine strings that were generated under this symmetry.
+    fn tag(self) -> &'static str {
+        match self {
+            TableSymmetry::None => "",
+            TableSymmetry::H => "sym-h:",
+            TableSymmetry::V => "sym-v:",
+            TableSymmetry::HV => "sym-hv:",
+        }
+    }
+
+    fn mirror(self, action: Action) -> Action {
+        match self {
+            TableSymmetry::None => action,
+            TableSymmetry::H => action.mirror_horizontal(),
+            TableSymmetry::V => action.mirror_vertical(),
+            TableSymmetry::HV => action.mirror_horizontal().mirror_vertical(),
+        }
+    }
+
+    /// Rewrites the second half of `table` so it mirrors the first half.
+    fn apply(self, table: &mut [Transition]) {
+        let len = table.len();
+        for i in 0..len / 2 {
+            table[len - 1 - i].action = self.mirror(table[i].action);
+        }
+    }
+
+    /// Whether the second half of `table` mirrors the first half. Always true without a
+    /// symmetry.
+    fn holds_for(self, table: &[Transition]) -> bool {
+        if self == TableSymmetry::None {
+            return true;
+        }
+        let len = table.len();
+        (0..len / 2).all(|i| table[len - 1 - i].action == self.mirror(table[i].action))
+    }
+}
+
+/// How often a generated transition gets one of the self-modifying actions when they are enabled.
+const SELF_MODIFY_CHANCE: f64 = 1.0 / 64.0;
+
+/// How a new machine picks the state it starts in.
+#[derive(Clone, Copy, PartialEq)]
+pub enum StartState {
+    Fixed(u8),
+    /// Any state, uniformly.
+    Random,
+    /// A random state, weighted by how many table entries lead into it, so machines tend to
+    /// start where their tables spend the most time.
+    Weighted,
+}
+
+/// Knobs for generating random tables.
+#[derive(Clone, Copy)]
+pub struct GenerationParams {
+    /// Probability that a transition's next state is forced back to state 0 instead of being
+    /// drawn uniformly.
+    pub sparse_bias: f64,
+    /// Whether generated tables may contain the self-modifying actions.
+    pub self_modify: bool,
+    /// Mirror constraint applied to the finished table.
+    pub symmetry: TableSymmetry,
+    pub start: StartState,
+    /// Byte planes the machine reads and writes, see `TuringMachine::layers`.
+    pub layers: u8,
+    /// Whether the machine reads the neighbourhood of the head as well as the cell under it.
+    pub sensing: bool,
+}
+
+/*
+N states, one start state
+K symbols
+4 actions (left, right up, down)
+N x K -> N x K x A
+*/
+
+impl TuringMachine {
+    /// Generates a random machine. A nonzero `sparse_bias` makes most of the table funnel
+    /// through the start state, which tends to produce more coherent patterns. Line machines
+    /// only ever get Left and Right moves. The same `rng` state always gives the same machine.
+    pub fn new<R: Rng + ?Sized>(
+        num_states: u16,
+        num_symbols: u16,
+        params: GenerationParams,
+        mode: Mode,
+        width: usize,
+        height: usize,
+        rng: &mut R,
+    ) -> TuringMachine {
+        assert_size(width, height);
+        if let Err(message) = check_generation(num_states, num_symbols, &params, mode) {
+            panic!("{}", message);
+        }
+
+        let senses = if params.sensing { SENSES as usize } else { 1 };
+        let strides = table_strides(FORMAT_VERSION, num_states, num_symbols, params.sensing);
+        let entries = num_states as usize * num_symbols as usize * senses;
+        let mut table = Vec::with_capacity(entries);
+        for _ in 0..entries {
+            let state = if rng.gen_bool(params.sparse_bias) {
+                0
+            } else {
+                rng.gen_range(0, num_states) as u8
+            };
+
+            let action = match mode {
+                _ if params.self_modify && rng.gen_bool(SELF_MODIFY_CHANCE) => {
+                    if rng.gen() {
+                        Action::SelfWrite
+                    } else {
+                        Action::SelfRead
+                    }
+                }
+                Mode::Plane => rng.gen(),
+                Mode::Line if rng.gen() => Action::Left,
+                Mode::Line => Action::Right,
+                Mode::Hex => HEX_ACTIONS[rng.gen_range(0, HEX_ACTIONS.len())],
+            };
+
+            let symbol = rng.gen_range(0, num_symbols) as u8;
+            let (read_layer, write_layer) = if params.layers > 1 {
+                (rng.gen_range(0, params.layers), rng.gen_range(0, params.layers))
+            } else {
+                (0, 0)
+            };
+
+            let trans = Transition {
+                state,
+                symbol,
+                action,
+                read_layer,
+                write_layer,
+                start: (state as usize * strides.0) as u32,
+            };
+
+            table.push(trans);
+        }
+        params.symmetry.apply(&mut table);
+        debug_assert!(params.symmetry.holds_for(&table));
+
+        let mut machine = TuringMachine {
+            table,
+            map: vec![0; width * height],
+            layers: vec![vec![0; width * height]; params.layers as usize - 1],
+            width,
+            height,
+            num_states,
+            num_symbols,
+            mode,
+            sensing: params.sensing,
+            symmetry: params.symmetry,
+            version: FORMAT_VERSION,
+            strides,
+            state: 0,
+            layer: 0,
+            xpos: 0,
+            ypos: 0,
+            itr_count: 0,
+            self_modifications: 0,
+            action_counts: [0; ACTIONS.len()],
+            pull: None,
+        };
+        machine.state = machine.pick_start_state(params.start, rng);
+        machine
+    }
+
+    /// Parses a machine string. Strings for line and hex machines carry a `1d:` or `hex:` prefix
+    /// in front of the usual comma separated numbers, and tables generated under a symmetry
+    /// carry its tag (`sym-h:`, `sym-v:` or `sym-hv:`), which is checked against the table. A
+    /// `v<n>:` prefix gives the format version, see `FORMAT_VERSION`. Machines with more than
+    /// one layer carry a `layers<n>:` prefix and two more numbers per entry: the layer the next
+    /// step reads from and the layer the symbol is written to. Sensing machines carry a `sense:`
+    /// prefix and `SENSES` entries for every state and symbol.
+    pub fn from_string(
+        transition_hash: &str,
+        width: usize,
+        height: usize,
+    ) -> Result<TuringMachine, MachineParseError> {
+        if !size_fits(width, height) {
+            return Err(MachineParseError::InvalidSize { width, height });
+        }
+        let mut mode = Mode::Plane;
+        let mut symmetry = TableSymmetry::None;
+        let mut version = 1;
+        let mut layers = 1;
+        let mut sensing = false;
+        let mut transition_hash = transition_hash;
+        loop {
+            if let Some((number, rest)) = transition_hash
+                .strip_prefix('v')
+                .and_then(|rest| rest.split_once(':'))
+                .filter(|(number, _)| u8::from_str(number).is_ok())
+            {
+                version = u8::from_str(number).unwrap();
+                if !(1..=FORMAT_VERSION).contains(&version) {
+                    return Err(MachineParseError::InvalidHeader(format!(
+                        "unsupported machine string version {}",
+                        version
+                    )));
+                }
+                transition_hash = rest;
+            } else if let Some((count, rest)) = transition_hash
+                .strip_prefix("layers")
+                .and_then(|rest| rest.split_once(':'))
+            {
+                layers = u8::from_str(count)
+                    .map_err(|_| MachineParseError::InvalidHeader(format!("layers{}: needs a count", count)))?;
+                transition_hash = rest;
+            } else if let Some(rest) = transition_hash.strip_prefix("1d:") {
+                mode = Mode::Line;
+                transition_hash = rest;
+            } else if let Some(rest) = transition_hash.strip_prefix("hex:") {
+                mode = Mode::Hex;
+                transition_hash = rest;
+            } else if let Some(rest) = transition_hash.strip_prefix("sense:") {
+                sensing = true;
+                transition_hash = rest;
+            } else if let Some((tagged, rest)) = [TableSymmetry::H, TableSymmetry::V, TableSymmetry::HV]
+                .iter()
+                .find_map(|&s| transition_hash.strip_prefix(s.tag()).map(|rest| (s, rest)))
+            {
+                symmetry = tagged;
+                transition_hash = rest;
+            } else {
+                break;
+            }
+        }
+
+        let numbers = transition_hash
+            .split(',')
+            .enumerate()
+            .map(|(position, text)| {
+                u8::from_str(text).map_err(|_| MachineParseError::UnparsableNumber {
+                    position,
+                    text: text.to_string(),
+                })
+            })
+            .collect::<Result<Vec<u8>, _>>()?;
+        if numbers.len() < 2 {
+            return Err(MachineParseError::TruncatedTable {
+                expected: 2,
+                found: numbers.len(),
+            });
+        }
+        let num_states = numbers[0] as u16;
+        let num_symbols = numbers[1] as u16;
+        check_table_size(num_states, num_symbols).map_err(MachineParseError::InvalidHeader)?;
+        check_layers(layers, mode).map_err(MachineParseError::InvalidHeader)?;
+        check_sensing(sensing, mode).map_err(MachineParseError::InvalidHeader)?;
+
+        let per_entry = if layers > 1 { 5 } else { 3 };
+        let senses = if sensing { SENSES as usize } else { 1 };
+        let expected = 2 + per_entry * num_states as usize * num_symbols as usize * senses;
+        if numbers.len() < expected {
+            return Err(MachineParseError::TruncatedTable {
+                expected,
+                found: numbers.len(),
+            });
+        }
+        if numbers.len() > expected {
+            return Err(MachineParseError::TrailingNumbers {
+                expected,
+                found: numbers.len(),
+            });
+        }
+
+        let strides = table_strides(version, num_states, num_symbols, sensing);
+        let mut table = Vec::with_capacity(expected / per_entry);
+        for (index, entry) in numbers[2..].chunks_exact(per_entry).enumerate() {
+            let (state, symbol) = (entry[0], entry[1]);
+            if state as u16 >= num_states {
+                return Err(MachineParseError::StateOutOfRange { state, index });
+            }
+            if symbol as u16 >= num_symbols {
+                return Err(MachineParseError::SymbolOutOfRange { symbol, index });
+            }
+
+            let action = match entry[2] {
+                0 => Action::Left,
+                1 => Action::Right,
+                2 => Action::Up,
+                3 => Action::Down,
+                4 => Action::UpLeft,
+                5 => Action::UpRight,
+                6 => Action::DownLeft,
+                7 => Action::DownRight,
+                8 => Action::SelfWrite,
+                9 => Action::SelfRead,
+                code => return Err(MachineParseError::InvalidAction { code, index }),
+            };
+            if let (Mode::Hex, Action::Up) | (Mode::Hex, Action::Down) = (mode, action) {
+                return Err(MachineParseError::StraightMoveOnHex { index });
+            }
+            let (read_layer, write_layer) = if layers > 1 { (entry[3], entry[4]) } else { (0, 0) };
+            if let Some(&layer) = [read_layer, write_layer].iter().find(|&&layer| layer >= layers) {
+                return Err(MachineParseError::LayerOutOfRange { layer, index });
+            }
+
+            let trans = Transition {
+                state,
+                symbol,
+                action,
+                read_layer,
+                write_layer,
+                start: (state as usize * strides.0) as u32,
+            };
+
+            table.push(trans);
+        }
+        if !symmetry.holds_for(&table) {
+            return Err(MachineParseError::BrokenSymmetry);
+        }
+
+        Ok(TuringMachine {
+            table,
+            map: vec![0; width * height],
+            layers: vec![vec![0; width * height]; layers as usize - 1],
+            width,
+            height,
+            num_states,
+            num_symbols,
+            mode,
+            sensing,
+            symmetry,
+            version,
+            strides,
+            state: 0,
+            layer: 0,
+            xpos: 0,
+            ypos: 0,
+            itr_count: 0,
+            self_modifications: 0,
+            action_counts: [0; ACTIONS.len()],
+            pull: None,
+        })
+    }
+
+    /// The table entry consulted when the machine is in `state` reading `symbol` with `sense`
+    /// nonzero neighbours. `sense` is always 0 for machines that don't sense.
+    fn transition(&self, state: u8, symbol: u8, sense: u8) -> &Transition {
+        &self.table[self.entry_index(state, symbol, sense)]
+    }
+
+    /// Position of the entry for `state`, `symbol` and `sense` in the table. States and symbols
+    /// are ordered as the format version the table was written for says, and the entries for the
+    /// senses of one state and symbol follow each other.
+    fn entry_index(&self, state: u8, symbol: u8, sense: u8) -> usize {
+        assert!((state as u16) < self.num_states, "no table entries for state {}", state);
+        let shape = (self.num_symbols, self.senses());
+        checked_table_index(state as usize * self.strides.0, self.strides.1, shape, symbol, sense)
+            .unwrap_or_else(|| panic!("no table entry for symbol {}, sense {}", symbol, sense))
+    }
+
+    pub fn is_sensing(&self) -> bool {
+        self.sensing
+    }
+
+    /// Table entries per state and symbol: `SENSES` for sensing machines, otherwise 1.
+    pub fn senses(&self) -> u8 {
+        if self.sensing {
+            SENSES
+        } else {
+            1
+        }
+    }
+
+    /// How many of the four cells next to the head are nonzero on the layer being read,
+    /// capped at `SENSES - 1`. Always 0 for machines that don't sense.
+    fn sense(&self) -> u8 {
+        if !self.sensing {
+            return 0;
+        }
+        let (x, y, width, height) = (self.xpos, self.ypos, self.width, self.height);
+        let plane = self.plane(self.layer);
+        let neighbours = [
+            width * y + wrapping_move(x, -1, width),
+            width * y + wrapping_move(x, 1, width),
+            width * wrapping_move(y, -1, height) + x,
+            width * wrapping_move(y, 1, height) + x,
+        ];
+        let nonzero = neighbours.iter().filter(|&&cell| plane[cell] != 0).count() as u8;
+        nonzero.min(SENSES - 1)
+    }
+
+    /// A start state for this machine's table.
+    pub fn pick_start_state<R: Rng + ?Sized>(&self, start: StartState, rng: &mut R) -> u8 {
+        match start {
+            StartState::Fixed(state) => {
+                assert!((state as u16) < self.num_states, "start state {} is out of range", state);
+                state
+            }
+            StartState::Random => rng.gen_range(0, self.num_states) as u8,
+            StartState::Weighted => {
+                let mut weights = vec![0u32; self.num_states as usize];
+                for trans in &self.table {
+                    weights[trans.state as usize] += 1;
+                }
+                let mut pick = rng.gen_range(0, self.table.len() as u32);
+                let mut state = 0;
+                while pick >= weights[state] {
+                    pick -= weights[state];
+                    state += 1;
+                }
+                state as u8
+            }
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.state = 0;
+        self.layer = 0;
+        self.ypos = 0;
+        self.xpos = 0;
+        self.itr_count = 0;
+        self.self_modifications = 0;
+        self.action_counts = [0; ACTIONS.len()];
+
+        self.map.fill(0);
+        for plane in &mut self.layers {
+            plane.fill(0);
+        }
+    }
+
+    /// FNV-1a hash of the machine's dimensions and table, stable across runs and platforms.
+    pub fn genome_hash(&self) -> u64 {
+        let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+        let mut feed = |byte: u8| {
+            hash ^= byte as u64;
+            hash = hash.wrapping_mul(0x0100_0000_01b3);
+        };
+
+        feed(self.num_states as u8);
+        feed(self.num_symbols as u8);
+        for trans in &self.table {
+            feed(trans.state);
+            feed(trans.symbol);
+            feed(trans.action as u8);
+        }
+        // Single layer machines hash as they did before layers existed.
+        if !self.layers.is_empty() {
+            feed(self.num_layers());
+            for trans in &self.table {
+                feed(trans.read_layer);
+                feed(trans.write_layer);
+            }
+        }
+        hash
+    }
+
+    pub fn num_layers(&self) -> u8 {
+        1 + self.layers.len() as u8
+    }
+
+    /// The cells of `layer`, with layer 0 being `map`.
+    pub fn plane(&self, layer: u8) -> &[u8] {
+        match layer {
+            0 => &self.map,
+            _ => &self.layers[layer as usize - 1],
+        }
+    }
+
+    fn plane_mut(&mut self, layer: u8) -> &mut [u8] {
+        match layer {
+            0 => &mut self.map,
+            _ => &mut self.layers[layer as usize - 1],
+        }
+    }
+
+    /// Index of the table entry used when the machine reads `symbol` in its current state and
+    /// neighbourhood.
+    fn table_index(&self, symbol: u8) -> usize {
+        self.entry_index(self.state, symbol, self.sense())
+    }
+
+    /// Reads the symbol under the head, overwrites it, switches state and returns the action the
+    /// transition asks for.
+    fn apply_transition(&mut self) -> Action {
+        let index = self.width * self.ypos + self.xpos;
+
+        let symbol = self.plane(self.layer)[index];
+        let trans = &self.table[self.table_index(symbol)];
+        let (state, symbol, action) = (trans.state, trans.symbol, trans.action);
+        let (read_layer, write_layer) = (trans.read_layer, trans.write_layer);
+
+        self.state = state;
+        self.plane_mut(write_layer)[index] = symbol;
+        self.layer = read_layer;
+
+        let action = match (action, self.pull.take()) {
+            (Action::SelfWrite, _) | (Action::SelfRead, _) => action,
+            (_, Some(pull)) => pull,
+            (action, None) if self.version < 2 => action.mirror_horizontal(),
+            (action, None) => action,
+        };
+        self.action_counts[action as usize] = self.action_counts[action as usize].wrapping_add(1);
+        match action {
+            Action::SelfWrite => self.self_write(index),
+            Action::SelfRead => self.self_read(index),
+            _ => {}
+        }
+        action
+    }
+
+    /// The table entry a self-modifying action touches, picked from the machine's state and
+    /// position so that the same machine reaches different entries as it wanders.
+    fn self_modify_index(&self) -> usize {
+        (self.state as usize + self.xpos + self.ypos) % self.table.len()
+    }
+
+    fn self_write(&mut self, index: usize) {
+        let entry = self.self_modify_index();
+        self.table[entry].symbol = self.map[index] % self.num_symbols as u8;
+        self.self_modifications = self.self_modifications.wrapping_add(1);
+    }
+
+    fn self_read(&mut self, index: usize) {
+        let entry = self.self_modify_index();
+        self.map[index] = self.table[entry].symbol;
+        self.self_modifications = self.self_modifications.wrapping_add(1);
+    }
+
+    /// Rows used by hex machines. Odd-row offset addressing only wraps cleanly with an even
+    /// number of rows, so an odd height leaves the last row unused.
+    pub fn hex_height(&self) -> usize {
+        self.height & !1
+    }
+
+    /// Moves the head by `dx`, `dy` on a map `height` rows tall, wrapping around the edges.
+    fn move_head(&mut self, dx: isize, dy: isize, height: usize) {
+        self.xpos = wrapping_move(self.xpos, dx, self.width);
+        self.ypos = wrapping_move(self.ypos, dy, height);
+    }
+
+    /// Moves the head of a plane machine as `action` says.
+    fn move_plane(&mut self, action: Action) {
+        let (dx, dy) = MOVES[action as usize];
+        self.move_head(dx, dy, self.height);
+    }
+
+    /// `step` for the plane machines most runs use: one layer, no sensing and no pull waiting.
+    /// Each step waits on the one before it, so the loop keeps that chain short: the head is
+    /// kept as its column and the offset of its row in the map, the next entry is found from
+    /// the `start` stored in the last one, and the move is a match the branch predictor can run
+    /// ahead of. Only the self-modifying actions, which need the whole machine, leave the inner
+    /// loop.
+    fn run_plane(&mut self, num_iters: u32) {
+        let (width, size) = (self.width, self.width * self.height);
+        let shape = (self.num_symbols, 1);
+        // Version 1 tables have their horizontal moves mirrored, see `FORMAT_VERSION`.
+        let flip = if self.version < 2 { -1 } else { 1 };
+        let (mut x, mut row, mut state) = (self.xpos, width * self.ypos, self.state);
+        let mut start = state as usize * self.strides.0;
+
+        let mut done = 0;
+        while done < num_iters {
+            let (map, table, counts) = (&mut self.map[..], &self.table[..], &mut self.action_counts);
+            let mut modify = None;
+            while done < num_iters {
+                done += 1;
+                let index = row + x;
+                let symbol = map[index];
+                let entry = checked_table_index(start, self.strides.1, shape, symbol, 0)
+                    .unwrap_or_else(|| panic!("no table entry for symbol {}", symbol));
+                let trans = &table[entry];
+                let action = trans.action;
+                state = trans.state;
+                start = trans.start as usize;
+                map[index] = trans.symbol;
+                // Counted as carried out, like `apply_transition` does, but the move below is
+                // flipped instead so it doesn't wait on the mirroring.
+                let carried_out = if flip < 0 { action.mirror_horizontal() } else { action };
+                counts[carried_out as usize] = counts[carried_out as usize].wrapping_add(1);
+
+                if let Action::SelfWrite | Action::SelfRead = action {
+                    modify = Some((action, index));
+                    break;
+                }
+                let (dx, dy) = match action {
+                    Action::Up => (0, -1),
+                    Action::Down => (0, 1),
+                    Action::Left => (-flip, 0),
+                    Action::Right => (flip, 0),
+                    Action::UpLeft => (-flip, -1),
+                    Action::UpRight => (flip, -1),
+                    Action::DownLeft => (-flip, 1),
+                    Action::DownRight => (flip, 1),
+                    Action::SelfWrite | Action::SelfRead => (0, 0),
+                };
+                x = wrapping_move(x, dx, width);
+                row = wrapping_move(row, dy * width as isize, size);
+            }
+
+            // The entry a self-modifying action touches depends on the new state and the
+            // position of the cell just written.
+            self.state = state;
+            self.xpos = x;
+            self.ypos = row / width;
+            match modify {
+                Some((Action::SelfWrite, index)) => self.self_write(index),
+                Some((_, index)) => self.self_read(index),
+                None => {}
+            }
+        }
+    }
+
+    /// Moves the head of a line machine. The head only moves along the current row, and after
+    /// every step the row is copied into the next row of the ring, which becomes the live tape.
+    fn move_line(&mut self, action: Action) {
+        match action {
+            Action::Left => self.xpos = wrapping_move(self.xpos, -1, self.width),
+            Action::Right => self.xpos = wrapping_move(self.xpos, 1, self.width),
+            _ => {}
+        }
+
+        let row = self.width * self.ypos;
+        let next = wrapping_move(self.ypos, 1, self.height);
+        self.map.copy_within(row..row + self.width, self.width * next);
+        self.ypos = next;
+    }
+
+    /// Moves the head of a hex machine. Odd rows sit half a cell further along +x than even
+    /// rows, so a diagonal move only changes x when it goes right from an odd row or left from
+    /// an even one.
+    fn move_hex(&mut self, action: Action) {
+        let odd_row = self.ypos % 2 == 1;
+        let left = if odd_row { 0 } else { -1 };
+        let right = if odd_row { 1 } else { 0 };
+        let (dx, dy) = match action {
+            Action::Left => (-1, 0),
+            Action::Right => (1, 0),
+            Action::UpLeft => (left, -1),
+            Action::UpRight => (right, -1),
+            Action::DownLeft => (left, 1),
+            Action::DownRight => (right, 1),
+            Action::Up | Action::Down | Action::SelfWrite | Action::SelfRead => return,
+        };
+        self.move_head(dx, dy, self.hex_height());
+    }
+
+    pub fn step(&mut self, num_iters: u32) {
+        match self.mode {
+            Mode::Plane if !self.sensing && self.layers.is_empty() && self.pull.is_none() => {
+                self.run_plane(num_iters)
+            }
+            Mode::Plane => {
+                for _ in 0..num_iters {
+                    let action = self.apply_transition();
+                    self.move_plane(action);
+                }
+            }
+            Mode::Line => {
+                for _ in 0..num_iters {
+                    let action = self.apply_transition();
+                    self.move_line(action);
+                }
+            }
+            Mode::Hex => {
+                for _ in 0..num_iters {
+                    let action = self.apply_transition();
+                    self.move_hex(action);
+                }
+            }
+        }
+        // Nothing in the loops reads the count, so it is bumped once.
+        self.itr_count = self.itr_count.wrapping_add(num_iters);
+    }
+
+    /// Runs a single step and returns the action that was carried out. That is the table's
+    /// action unless a pull replaced it or the table was written for version 1, which mirrors
+    /// it.
+    pub fn step_once(&mut self) -> Action {
+        let action = self.apply_transition();
+        match self.mode {
+            Mode::Plane => self.move_plane(action),
+            Mode::Line => self.move_line(action),
+            Mode::Hex => self.move_hex(action),
+        }
+        self.itr_count = self.itr_count.wrapping_add(1);
+        action
+    }
+
+    /// The buffer to put on screen. Plane machines show their map as is. Line machines rotate
+    /// their ring of rows so the live row is at the top of the window and older rows fall
+    /// towards the bottom.
+    pub fn display<'a>(&'a self, scratch: &'a mut Vec<u8>) -> &'a [u8] {
+        match self.mode {
+            Mode::Plane | Mode::Hex => &self.map[..],
+            Mode::Line => {
+                let split = self.width * (self.ypos + 1);
+                scratch.clear();
+                scratch.extend_from_slice(&self.map[split..]);
+                scratch.extend_from_slice(&self.map[..split]);
+                &scratch[..]
+            }
+        }
+    }
+}
+
+/// The `strides` of a table laid out for `version`. From version 3 the entries of one state
+/// follow each other, before that the entries of one symbol did. A sensing machine's entries for
+/// one state and symbol always sit together.
+fn table_strides(version: u8, num_states: u16, num_symbols: u16, sensing: bool) -> (usize, usize) {
+    let senses = if sensing { SENSES as usize } else { 1 };
+    if version < 3 {
+        (senses, num_states as usize * senses)
+    } else {
+        (num_symbols as usize * senses, senses)
+    }
+}
+
+/// `pos` moved `delta` cells along an axis of `len` cells that wraps around. The move may be at
+/// most one lap either way, which covers every step, stamp and splat on a map that fits.
+pub(crate) fn wrapping_move(pos: usize, delta: isize, len: usize) -> usize {
+    debug_assert!(pos < len && delta.unsigned_abs() <= len);
+    // A move below 0 wraps around to a huge `moved`, so one comparison catches leaving the axis
+    // at either end.
+    let moved = pos.wrapping_add(delta as usize);
+    let wrapped = if delta < 0 { moved.wrapping_add(len) } else { moved.wrapping_sub(len) };
+    if moved < len {
+        moved
+    } else {
+        wrapped
+    }
+}
+
+/// Position of the entry for `symbol` and `sense` of the state whose entries start at `start`,
+/// in a table whose neighbouring symbols are `symbol_stride` entries apart. `shape` is the
+/// number of symbols and senses; `None` when either is out of range for it, rather than an
+/// index into the entries of some other state or past the end.
+pub(crate) fn checked_table_index(
+    start: usize,
+    symbol_stride: usize,
+    shape: (u16, u8),
+    symbol: u8,
+    sense: u8,
+) -> Option<usize> {
+    let (num_symbols, senses) = shape;
+    if symbol as u16 >= num_symbols || sense >= senses {
+        return None;
+    }
+    Some(start + symbol as usize * symbol_stride + sense as usize)
+}
+
+/// Maps need at least two cells each way; stamps and splats reach two cells past the head.
+pub(crate) fn size_fits(width: usize, height: usize) -> bool {
+    width >= 2 && height >= 2
+}
+
+fn assert_size(width: usize, height: usize) {
+    assert!(size_fits(width, height), "the map must be at least 2x2");
+}
+
+/// Everything `TuringMachine::new` needs from its arguments, besides the map size.
+pub(crate) fn check_generation(
+    num_states: u16,
+    num_symbols: u16,
+    params: &GenerationParams,
+    mode: Mode,
+) -> Result<(), String> {
+    check_table_size(num_states, num_symbols)?;
+    if !(0.0..=1.0).contains(&params.sparse_bias) {
+        return Err("sparse_bias must be between 0 and 1".to_string());
+    }
+    if params.symmetry != TableSymmetry::None && !(num_states * num_symbols).is_multiple_of(2) {
+        return Err("symmetric tables need an even number of entries".to_string());
+    }
+    if let StartState::Fixed(state) = params.start {
+        if state as u16 >= num_states {
+            return Err(format!("start state {} is out of range", state));
+        }
+    }
+    check_layers(params.layers, mode)?;
+    check_sensing(params.sensing, mode)
+}
+
+/// The neighbourhood is the four square neighbours, which only the square plane has.
+fn check_sensing(sensing: bool, mode: Mode) -> Result<(), String> {
+    if sensing && mode != Mode::Plane {
+        return Err("only square plane machines can sense their neighbours".to_string());
+    }
+    Ok(())
+}
+
+/// Counts are stored as u8 in machine strings and states as u8 in the table. Symbols stay below
+/// the values the screen draws markers with, see `screen::INSPECT_MARKER`. Every state and
+/// symbol pair needs a slot in the table, `SENSES` slots for sensing machines, and the pairs are
+/// capped at 4096 so tables stay small enough to print and hash.
+fn check_table_size(num_states: u16, num_symbols: u16) -> Result<(), String> {
+    if !(1..=255).contains(&num_states) {
+        return Err("must have between 1 and 255 states".to_string());
+    }
+    if !(2..=254).contains(&num_symbols) {
+        return Err("must have between 2 and 254 symbols".to_string());
+    }
+    if num_states as usize * num_symbols as usize > 4096 {
+        return Err("num_states * num_symbols <= 4096".to_string());
+    }
+    Ok(())
+}
+
+/// Extra layers are only drawn and stepped on the square plane.
+fn check_layers(layers: u8, mode: Mode) -> Result<(), String> {
+    if !(1..=MAX_LAYERS).contains(&layers) {
+        return Err(format!("must have between 1 and {} layers", MAX_LAYERS));
+    }
+    if layers > 1 && mode != Mode::Plane {
+        return Err("only square plane machines can have more than 1 layer".to_string());
+    }
+    Ok(())
+}
+
+/// Why a machine string couldn't be parsed. Positions count the comma separated numbers from
+/// 0, which is `num_states`, and indices count table entries from 0.
+#[derive(Debug, PartialEq)]
+pub enum MachineParseError {
+    /// Something before the table is wrong: an unsupported version, a bad layer count or a
+    /// table size out of range.
+    InvalidHeader(String),
+    /// The map the machine was asked to run on is smaller than 2x2.
+    InvalidSize { width: usize, height: usize },
+    UnparsableNumber { position: usize, text: String },
+    /// Fewer numbers than the header asks for.
+    TruncatedTable { expected: usize, found: usize },
+    /// More numbers than the header asks for.
+    TrailingNumbers { expected: usize, found: usize },
+    InvalidAction { code: u8, index: usize },
+    /// An Up or Down move in a hex machine.
+    StraightMoveOnHex { index: usize },
+    StateOutOfRange { state: u8, index: usize },
+    SymbolOutOfRange { symbol: u8, index: usize },
+    LayerOutOfRange { layer: u8, index: usize },
+    /// The table doesn't have the symmetry it is tagged with.
+    BrokenSymmetry,
+}
+
+impl fmt::Display for MachineParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MachineParseError::InvalidHeader(message) => write!(f, "{}", message),
+            MachineParseError::InvalidSize { width, height } => {
+                write!(f, "the map must be at least 2x2, not {}x{}", width, height)
+            }
+            MachineParseError::UnparsableNumber { position, text } => {
+                write!(f, "number {} (\"{}\") is not a number from 0 to 255", position, text)
+            }
+            MachineParseError::TruncatedTable { expected, found } => {
+                write!(f, "table ends early: expected {} numbers, found {}", expected, found)
+            }
+            MachineParseError::TrailingNumbers { expected, found } => {
+                write!(f, "table is too long: expected {} numbers, found {}", expected, found)
+            }
+            MachineParseError::BrokenSymmetry => write!(f, "table doesn't have the symmetry it is tagged with"),
+            _ => {
+                let (index, problem) = self.entry_problem().expect("the other errors are all about one entry");
+                write!(f, "entry {}: {}", index, problem)
+            }
+        }
+    }
+}
+
+impl MachineParseError {
+    /// For a problem with a single table entry, the entry's index and what is wrong with it.
+    pub fn entry_problem(&self) -> Option<(usize, String)> {
+        Some(match *self {
+            MachineParseError::InvalidAction { code, index } => (index, format!("no action has code {}", code)),
+            MachineParseError::StraightMoveOnHex { index } => {
+                (index, "hex machines can't move straight up or down".to_string())
+            }
+            MachineParseError::StateOutOfRange { state, index } => (index, format!("state {} is out of range", state)),
+            MachineParseError::SymbolOutOfRange { symbol, index } => {
+                (index, format!("symbol {} is out of range", symbol))
+            }
+            MachineParseError::LayerOutOfRange { layer, index } => (index, format!("layer {} is out of range", layer)),
+            _ => return None,
+        })
+    }
+}
+
+impl std::error::Error for MachineParseError {}
+
+/// Writes the machine string that `from_string` parses back into the same table.
+impl fmt::Display for TuringMachine {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let prefix = match self.mode {
+            Mode::Plane => "",
+            Mode::Line => "1d:",
+            Mode::Hex => "hex:",
+        };
+        let sense = if self.sensing { "sense:" } else { "" };
+        if self.version > 1 {
+            write!(f, "v{}:", self.version)?;
+        }
+        // Self-modification can break the symmetry a table was generated with, and the tag
+        // would then stop the string from parsing.
+        let tag = if self.symmetry.holds_for(&self.table) {
+            self.symmetry.tag()
+        } else {
+            ""
+        };
+        write!(f, "{}{}{}", prefix, sense, tag)?;
+        if !self.layers.is_empty() {
+            write!(f, "layers{}:", self.num_layers())?;
+        }
+        write!(f, "{},{}", self.num_states, self.num_symbols)?;
+        for trans in &self.table {
+            write!(f, ",{},{},{}", trans.state, trans.symbol, trans.action.code())?;
+            if !self.layers.is_empty() {
+                write!(f, ",{},{}", trans.read_layer, trans.write_layer)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rand::{rngs::SmallRng, SeedableRng};
+
+    fn params() -> GenerationParams {
+        GenerationParams {
+            sparse_bias: 0.0,
+            self_modify: false,
+            symmetry: TableSymmetry::None,
+            start: StartState::Fixed(0),
+            layers: 1,
+            sensing: false,
+        }
+    }
+
+    #[test]
+    fn smallest_maps_step_without_overflow() {
+        let mut rng = SmallRng::seed_from_u64(263);
+        for &(width, height) in &[(2, 2), (2, 9), (9, 2)] {
+            for &mode in &[Mode::Plane, Mode::Line, Mode::Hex] {
+                let mut machine = TuringMachine::new(4, 2, params(), mode, width, height, &mut rng);
+                machine.step(10_000);
+                assert!(machine.xpos < width && machine.ypos < height);
+                assert!(machine.map.iter().all(|&cell| cell < 2));
+            }
+        }
+    }
+
+    #[test]
+    fn largest_tables_step_without_overflow() {
+        let mut rng = SmallRng::seed_from_u64(263);
+        let mut machine = TuringMachine::new(255, 16, params(), Mode::Plane, 64, 64, &mut rng);
+        machine.step(10_000);
+        let mut machine = TuringMachine::new(16, 254, params(), Mode::Plane, 64, 64, &mut rng);
+        machine.step(10_000);
+    }
+
+    /// A plane machine on a `width` x `height` map that always takes `action`, starting at `x`,
+    /// `y`.
+    fn mover(action: Action, width: usize, height: usize, x: usize, y: usize) -> TuringMachine {
+        let code = action.code();
+        let text = format!("v3:1,2,0,0,{},0,0,{}", code, code);
+        let mut machine = TuringMachine::from_string(&text, width, height).unwrap();
+        machine.xpos = x;
+        machine.ypos = y;
+        machine
+    }
+
+    #[test]
+    fn each_move_wraps_at_the_edges() {
+        let (width, height) = (6, 5);
+        let cases = [
+            (Action::Left, (2, 2), (1, 2)),
+            (Action::Left, (0, 2), (5, 2)),
+            (Action::Right, (2, 2), (3, 2)),
+            (Action::Right, (5, 2), (0, 2)),
+            (Action::Up, (2, 2), (2, 1)),
+            (Action::Up, (2, 0), (2, 4)),
+            (Action::Down, (2, 2), (2, 3)),
+            (Action::Down, (2, 4), (2, 0)),
+            (Action::UpLeft, (2, 2), (1, 1)),
+            (Action::UpRight, (2, 2), (3, 1)),
+            (Action::DownLeft, (2, 2), (1, 3)),
+            (Action::DownRight, (2, 2), (3, 3)),
+            (Action::SelfWrite, (2, 2), (2, 2)),
+            (Action::SelfRead, (2, 2), (2, 2)),
+        ];
+        for &(action, (x, y), expected) in &cases {
+            let mut machine = mover(action, width, height, x, y);
+            machine.step(1);
+            assert_eq!((machine.xpos, machine.ypos), expected, "{} from {:?}", action.name(), (x, y));
+        }
+    }
+
+    #[test]
+    fn wraps_on_a_3x7_map() {
+        for &(width, height) in &[(3, 7), (7, 3)] {
+            for &(action, dx, dy) in &[
+                (Action::Left, width - 1, 0),
+                (Action::Right, 1, 0),
+                (Action::Up, 0, height - 1),
+                (Action::Down, 0, 1),
+            ] {
+                let mut machine = mover(action, width, height, 0, 0);
+                for step in 1..=2 * width * height {
+                    machine.step(1);
+                    let expected = ((dx * step) % width, (dy * step) % height);
+                    assert_eq!((machine.xpos, machine.ypos), expected, "{} on {}x{}", action.name(), width, height);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn version_1_strings_keep_mirrored_moves() {
+        // Code 0 is Left, which version 1 strings carried out as a move to +x.
+        let mut legacy = TuringMachine::from_string("1,2,0,0,0,0,0,0", 6, 5).unwrap();
+        let mut current = TuringMachine::from_string("v3:1,2,0,0,0,0,0,0", 6, 5).unwrap();
+        legacy.step(1);
+        current.step(1);
+        assert_eq!((legacy.xpos, current.xpos), (1, 5));
+        assert_eq!(legacy.to_string(), "1,2,0,0,0,0,0,0");
+    }
+
+    #[test]
+    fn samples_every_plane_move() {
+        let mut rng = SmallRng::seed_from_u64(274);
+        let mut seen = [false; ACTIONS.len()];
+        for _ in 0..1000 {
+            let action: Action = rng.gen();
+            seen[action as usize] = true;
+        }
+        assert_eq!(seen, [true, true, true, true, true, true, true, true, false, false]);
+    }
+
+    #[test]
+    fn diagonals_wrap_at_every_corner() {
+        let (width, height) = (5, 4);
+        let cases = [
+            (Action::UpLeft, (0, 0), (4, 3)),
+            (Action::UpRight, (4, 0), (0, 3)),
+            (Action::DownLeft, (0, 3), (4, 0)),
+            (Action::DownRight, (4, 3), (0, 0)),
+            // Moving away from the corner doesn't wrap.
+            (Action::DownRight, (0, 0), (1, 1)),
+            (Action::DownLeft, (4, 0), (3, 1)),
+            (Action::UpRight, (0, 3), (1, 2)),
+            (Action::UpLeft, (4, 3), (3, 2)),
+        ];
+        for &(action, (x, y), expected) in &cases {
+            let mut machine = mover(action, width, height, x, y);
+            machine.step(1);
+            assert_eq!((machine.xpos, machine.ypos), expected, "{} from {:?}", action.name(), (x, y));
+        }
+    }
+
+    #[test]
+    fn machine_strings_round_trip() {
+        let mut rng = SmallRng::seed_from_u64(251);
+        let variants = [
+            (Mode::Plane, params()),
+            (Mode::Line, params()),
+            (Mode::Hex, params()),
+            (Mode::Plane, GenerationParams { layers: 3, ..params() }),
+            (Mode::Plane, GenerationParams { sensing: true, ..params() }),
+            (Mode::Plane, GenerationParams { symmetry: TableSymmetry::HV, ..params() }),
+            (Mode::Plane, GenerationParams { self_modify: true, ..params() }),
+        ];
+        for &(mode, params) in &variants {
+            let machine = TuringMachine::new(6, 4, params, mode, 8, 8, &mut rng);
+            let text = machine.to_string();
+            let parsed = TuringMachine::from_string(&text, 8, 8).unwrap();
+            assert_eq!(parsed.to_string(), text);
+            assert_eq!(parsed.genome_hash(), machine.genome_hash());
+            assert!(parsed.mode == mode && parsed.is_sensing() == params.sensing);
+            assert_eq!(parsed.num_layers(), params.layers);
+        }
+    }
+
+    fn parse_error(text: &str) -> MachineParseError {
+        TuringMachine::from_string(text, 8, 8).err().expect("the string should not parse")
+    }
+
+    #[test]
+    fn reports_each_parse_error() {
+        assert_eq!(
+            parse_error("v9:1,2,0,0,0,0,0,0"),
+            MachineParseError::InvalidHeader("unsupported machine string version 9".to_string())
+        );
+        assert_eq!(
+            parse_error("1,1,0,0,0"),
+            MachineParseError::InvalidHeader("must have between 2 and 254 symbols".to_string())
+        );
+        assert_eq!(
+            TuringMachine::from_string("1,2,0,0,0,0,0,0", 1, 8).err(),
+            Some(MachineParseError::InvalidSize { width: 1, height: 8 })
+        );
+        assert_eq!(
+            parse_error("1,2,0,x,0,0,0,0"),
+            MachineParseError::UnparsableNumber {
+                position: 3,
+                text: "x".to_string()
+            }
+        );
+        assert_eq!(
+            parse_error("1,2,0,0,0,0,0"),
+            MachineParseError::TruncatedTable { expected: 8, found: 7 }
+        );
+        assert_eq!(
+            parse_error("1,2,0,0,0,0,0,0,0"),
+            MachineParseError::TrailingNumbers { expected: 8, found: 9 }
+        );
+        assert_eq!(
+            parse_error("1,2,0,0,0,0,0,10"),
+            MachineParseError::InvalidAction { code: 10, index: 1 }
+        );
+        assert_eq!(
+            parse_error("hex:1,2,0,0,0,0,0,2"),
+            MachineParseError::StraightMoveOnHex { index: 1 }
+        );
+        assert_eq!(
+            parse_error("1,2,0,0,0,1,0,0"),
+            MachineParseError::StateOutOfRange { state: 1, index: 1 }
+        );
+        assert_eq!(
+            parse_error("1,2,0,2,0,0,0,0"),
+            MachineParseError::SymbolOutOfRange { symbol: 2, index: 0 }
+        );
+        assert_eq!(
+            parse_error("layers2:1,2,0,0,0,0,2,0,0,0,0,1"),
+            MachineParseError::LayerOutOfRange { layer: 2, index: 0 }
+        );
+        assert_eq!(parse_error("sym-h:1,2,0,0,0,0,0,0"), MachineParseError::BrokenSymmetry);
+    }
+
+    #[test]
+    fn sensing_machine_turns_left_on_two_neighbours() {
+        // Every entry writes 0 and moves Up, except the ones for two nonzero neighbours, which
+        // turn Left.
+        let entry = |sense| if sense == 2 { "0,0,0" } else { "0,0,2" };
+        let entries: Vec<&str> = (0..2 * SENSES).map(|i| entry(i % SENSES)).collect();
+        let text = format!("v3:sense:1,2,{}", entries.join(","));
+        let neighbours = [(1, 2), (3, 2), (2, 1), (2, 3)];
+        for nonzero in 0..=neighbours.len() {
+            let mut machine = TuringMachine::from_string(&text, 5, 5).unwrap();
+            assert!(machine.is_sensing());
+            for &(x, y) in &neighbours[..nonzero] {
+                machine.map[5 * y + x] = 1;
+            }
+            machine.xpos = 2;
+            machine.ypos = 2;
+            let expected = if nonzero == 2 { Action::Left } else { Action::Up };
+            assert!(machine.step_once() == expected, "{} nonzero neighbours", nonzero);
+        }
+    }
+
+    #[test]
+    fn table_entries_are_distinct_for_50_states_and_64_symbols() {
+        let mut rng = SmallRng::seed_from_u64(258);
+        for &sensing in &[false, true] {
+            let params = GenerationParams { sensing, ..params() };
+            let mut machine = TuringMachine::new(50, 64, params, Mode::Plane, 8, 8, &mut rng);
+            for version in 1..=FORMAT_VERSION {
+                machine.version = version;
+                machine.strides = table_strides(version, 50, 64, sensing);
+                let mut seen = vec![false; machine.table.len()];
+                for state in 0..50 {
+                    for symbol in 0..64 {
+                        for sense in 0..machine.senses() {
+                            let index = machine.entry_index(state, symbol, sense);
+                            let at = (version, state, symbol, sense);
+                            assert!(index < seen.len() && !seen[index], "{:?}", at);
+                            seen[index] = true;
+                        }
+                    }
+                }
+                assert!(seen.iter().all(|&hit| hit));
+            }
+        }
+    }
+
+    #[test]
+    fn sparse_bias_sends_that_share_of_entries_to_state_0() {
+        let mut rng = SmallRng::seed_from_u64(202);
+        for &bias in &[0.0, 0.5, 0.9] {
+            let params = GenerationParams { sparse_bias: bias, ..params() };
+            let machine = TuringMachine::new(50, 64, params, Mode::Plane, 8, 8, &mut rng);
+            let to_start = machine.table.iter().filter(|trans| trans.state == 0).count();
+            let share = to_start as f64 / machine.table.len() as f64;
+            // The entries that aren't forced still land on state 0 one time in 50.
+            let expected = bias + (1.0 - bias) / 50.0;
+            assert!((share - expected).abs() < 0.03, "bias {} gave {}", bias, share);
+        }
+    }
+
+    #[test]
+    fn symmetric_tables_balance_mirrored_moves() {
+        let mut rng = SmallRng::seed_from_u64(234);
+        for &symmetry in &[TableSymmetry::H, TableSymmetry::V, TableSymmetry::HV] {
+            let params = GenerationParams { symmetry, ..params() };
+            let machine = TuringMachine::new(12, 8, params, Mode::Plane, 8, 8, &mut rng);
+            let count = |action| machine.table.iter().filter(|trans| trans.action == action).count();
+            let balanced: &[(Action, Action)] = match symmetry {
+                TableSymmetry::H => &[
+                    (Action::Left, Action::Right),
+                    (Action::UpLeft, Action::UpRight),
+                    (Action::DownLeft, Action::DownRight),
+                ],
+                TableSymmetry::V => &[
+                    (Action::Up, Action::Down),
+                    (Action::UpLeft, Action::DownLeft),
+                    (Action::UpRight, Action::DownRight),
+                ],
+                _ => &[
+                    (Action::Left, Action::Right),
+                    (Action::Up, Action::Down),
+                    (Action::UpLeft, Action::DownRight),
+                    (Action::UpRight, Action::DownLeft),
+                ],
+            };
+            for &(action, mirrored) in balanced {
+                assert_eq!(count(action), count(mirrored), "{} under {}", action.name(), symmetry.tag());
+            }
+
+            // The tag is written and checked against the table when the string is parsed.
+            let text = machine.to_string();
+            assert!(text.starts_with(&format!("v{}:{}", FORMAT_VERSION, symmetry.tag())), "{}", text);
+            assert!(TuringMachine::from_string(&text, 8, 8).is_ok());
+        }
+    }
+
+    #[test]
+    fn wrapping_moves_stay_on_the_axis() {
+        for len in 2..6 {
+            for pos in 0..len {
+                for delta in -(len as isize)..=len as isize {
+                    let expected = (pos as isize + delta).rem_euclid(len as isize) as usize;
+                    assert_eq!(wrapping_move(pos, delta, len), expected);
+                }
+            }
+        }
+        let len = usize::MAX / 2;
+        assert_eq!(wrapping_move(len - 1, 1, len), 0);
+        assert_eq!(wrapping_move(0, -1, len), len - 1);
+        assert_eq!(wrapping_move(len - 1, len as isize, len), len - 1);
+    }
+
+    #[test]
+    fn table_indices_are_checked() {
+        for &version in &[2, 3] {
+            for &sensing in &[false, true] {
+                let senses = if sensing { SENSES } else { 1 };
+                let strides = table_strides(version, 255, 16, sensing);
+                let shape = (16, senses);
+                let last = checked_table_index(254 * strides.0, strides.1, shape, 15, senses - 1);
+                assert_eq!(last, Some(255 * 16 * senses as usize - 1));
+                assert_eq!(checked_table_index(0, strides.1, shape, 0, 0), Some(0));
+                assert_eq!(checked_table_index(0, strides.1, shape, 16, 0), None);
+                assert_eq!(checked_table_index(0, strides.1, shape, 0, senses), None);
+            }
+        }
+        let strides = table_strides(FORMAT_VERSION, 16, 254, false);
+        assert_eq!(checked_table_index(15 * strides.0, strides.1, (254, 1), 253, 0), Some(16 * 254 - 1));
+        assert_eq!(checked_table_index(15 * strides.0, strides.1, (254, 1), 254, 0), None);
+    }
+
+    #[test]
+    fn every_entry_starts_at_its_next_state() {
+        let mut rng = SmallRng::seed_from_u64(286);
+        let generated = TuringMachine::new(7, 5, GenerationParams { sensing: true, ..params() }, Mode::Plane, 8, 8, &mut rng);
+        let parsed = TuringMachine::from_string("3,6,2,2,3,2,4,0,0,1,0,2,1,2,1,1,0,1,2,3,2,3,0,2,1,0,2,5,3,2,5,2,2,4,1,1,5,0,2,4,3,0,4,0,0,1,1,2,1,3,2,1,0,2,2,0", 8, 8).unwrap();
+        for machine in &[generated, parsed] {
+            for trans in &machine.table {
+                assert_eq!(trans.start as usize, machine.entry_index(trans.state, 0, 0));
+            }
+        }
+    }
+
+    #[test]
+    fn plane_loop_matches_single_steps() {
+        let mut rng = SmallRng::seed_from_u64(286);
+        let self_modifying = TuringMachine::new(6, 4, GenerationParams { self_modify: true, ..params() }, Mode::Plane, 24, 16, &mut rng);
+        let genomes = [
+            "3,6,2,2,3,2,4,0,0,1,0,2,1,2,1,1,0,1,2,3,2,3,0,2,1,0,2,5,3,2,5,2,2,4,1,1,5,0,2,4,3,0,4,0,0,1,1,2,1,3,2,1,0,2,2,0".to_string(),
+            "v3:2,3,1,1,4,0,2,7,1,0,5,0,1,6,1,2,1,0,0,3".to_string(),
+            self_modifying.to_string(),
+        ];
+        for genome in &genomes {
+            let mut looped = TuringMachine::from_string(genome, 24, 16).unwrap();
+            let mut single = TuringMachine::from_string(genome, 24, 16).unwrap();
+            looped.step(20_000);
+            for _ in 0..20_000 {
+                single.step_once();
+            }
+            assert!(looped.map == single.map, "{}", genome);
+            assert_eq!(looped.to_string(), single.to_string());
+            assert_eq!((looped.xpos, looped.ypos, looped.state), (single.xpos, single.ypos, single.state));
+            assert_eq!(looped.action_counts, single.action_counts);
+            assert_eq!(looped.self_modifications, single.self_modifications);
+        }
+    }
+
+    #[test]
+    fn iteration_count_wraps() {
+        let mut rng = SmallRng::seed_from_u64(263);
+        for &mode in &[Mode::Plane, Mode::Line, Mode::Hex] {
+            let mut machine = TuringMachine::new(2, 2, params(), mode, 8, 8, &mut rng);
+            machine.itr_count = u32::MAX - 1;
+            machine.step(5);
+            assert_eq!(machine.itr_count, 3);
+        }
+    }
+
+    #[test]
+    fn steps_write_then_move() {
+        // Writes 1 over 0 and 0 over 1, always moving right.
+        let mut machine = TuringMachine::from_string("v3:1,2,0,1,1,0,0,1", 4, 2).unwrap();
+        assert!(machine.step_once() == Action::Right);
+        assert_eq!((machine.xpos, machine.ypos, machine.itr_count), (1, 0, 1));
+        assert_eq!(machine.map, [1, 0, 0, 0, 0, 0, 0, 0]);
+
+        machine.step(3);
+        assert_eq!((machine.xpos, machine.ypos, machine.itr_count), (0, 0, 4));
+        assert_eq!(machine.map, [1, 1, 1, 1, 0, 0, 0, 0]);
+        machine.step(4);
+        assert!(machine.map.iter().all(|&cell| cell == 0));
+    }
+
+    #[test]
+    fn stepping_in_chunks_matches_one_call() {
+        for &mode in &[Mode::Plane, Mode::Line, Mode::Hex] {
+            let layers = if mode == Mode::Plane { 2 } else { 1 };
+            let params = GenerationParams { self_modify: true, layers, ..params() };
+            let build = || TuringMachine::new(6, 4, params, mode, 32, 24, &mut SmallRng::seed_from_u64(253));
+            let (mut whole, mut chunked) = (build(), build());
+
+            whole.step(30_000);
+            for _ in 0..30 {
+                chunked.step(999);
+                chunked.step_once();
+            }
+            assert!(chunked.map == whole.map && chunked.layers == whole.layers);
+            assert_eq!(chunked.to_string(), whole.to_string());
+            assert_eq!((chunked.xpos, chunked.ypos, chunked.state), (whole.xpos, whole.ypos, whole.state));
+            assert_eq!(chunked.itr_count, 30_000);
+        }
+    }
+}
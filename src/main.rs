@@ -1,290 +1,1584 @@
 use mini_gl_fb::glutin::{MouseButton, VirtualKeyCode};
 use mini_gl_fb::BufferFormat;
 
-use rand::prelude::*;
-use rand::{
-    distributions::{Distribution, Standard},
-    Rng,
-};
-
+use std::env;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
 use std::str::FromStr;
 use std::time::{SystemTime, UNIX_EPOCH};
 
-use arrayvec::ArrayVec;
-use screenshot_rs::screenshot_window;
+use image::RgbImage;
+
+use rand::rngs::SmallRng;
+use rand::{Rng, SeedableRng};
+
+use art::brush::Brush;
+use art::decay::Decay;
+use art::fitness::Fitness;
+use art::gravity::Gravity;
+use art::inspect;
+use art::metadata;
+use art::palette::{self, Palette};
+use art::paths::PathRecorder;
+use art::record::Recorder;
+use art::rewind::Rewind;
+use art::save;
+use art::screen::Screen;
+use art::shader::Shading;
+use art::simulation::Stats;
+use art::splat::Splat;
+use art::trace::{Before, Trace};
+use art::{genome_image, signature};
+use art::{GenerationParams, Mode, StartState, TableSymmetry, TuringMachine, ACTIONS, DEFAULT_HEIGHT, DEFAULT_WIDTH};
+
+mod repl;
+mod speed;
+
+use art::command::{Command, Setting};
+use speed::SpeedController;
 
-const WIDTH: usize = 1024;
-const HEIGHT: usize = 1025;
+const STEPS_PER_FRAME: u32 = 50_000;
+/// Upper limit for the + key.
+const MAX_KEY_STEPS_PER_FRAME: u32 = 100_000;
+/// A line machine adds one row to its space-time diagram per step, so it is run much slower
+/// than a plane machine to let the diagram scroll at a watchable pace.
+const LINE_STEPS_PER_FRAME: u32 = 16;
 
-enum Action {
-    Up,
-    Down,
-    Left,
-    Right,
+struct Options {
+    screenshot_dir: PathBuf,
+    generation: GenerationParams,
+    rewind_snapshots: usize,
+    rewind_interval: u32,
+    mode: Mode,
+    trace_first: Option<PathBuf>,
+    trace_rows: u64,
+    stamp_milestones: Vec<u32>,
+    decay: Decay,
+    paths_svg: Option<PathBuf>,
+    paths_max_points: usize,
+    target_fps: Option<f64>,
+    min_steps: u32,
+    max_steps: u32,
+    gravity_probability: f64,
+    gravity_interval: u32,
+    splat_size: Option<usize>,
+    splat_strength: f64,
+    /// Overrides the per-mode default. Zero is allowed and leaves the map untouched, which is
+    /// useful for just looking at it.
+    steps_per_frame: Option<u32>,
+    /// Window pixels per map cell.
+    scale: usize,
+    palette: Palette,
+    width: usize,
+    height: usize,
+    /// Run this many steps without a window, save a PNG and exit.
+    headless: Option<u32>,
+    /// Steps to run before the window opens.
+    warmup: u32,
+    /// Seed for every random choice in the run. A fresh one is picked and printed if not given.
+    seed: Option<u64>,
+    /// Save file to start from. F5 and F9 save to and load from here too.
+    load: Option<PathBuf>,
+    /// Frames between stats reports.
+    stats_every: u32,
+    verbose_stats: bool,
+    /// Print the frame rate every frame and the share of each action every second.
+    frame_log: bool,
+    /// CSV file the stats reports are appended to.
+    stats_file: Option<PathBuf>,
+    /// Random machines to pick the starting machine from.
+    tournament: Option<u32>,
+    tournament_steps: u32,
+    fitness: Fitness,
+    /// JSON machine definition to start with instead of the built in one.
+    machine: Option<PathBuf>,
 }
 
-impl Distribution<Action> for Standard {
-    fn sample<R: Rng + ?Sized>(&self, rng: &mut R) -> Action {
-        match rng.gen_range(0, 4) {
-            0 => Action::Up,
-            1 => Action::Down,
-            2 => Action::Left,
-            _ => Action::Right,
+impl Options {
+    fn from_args() -> Options {
+        let mut options = Options {
+            screenshot_dir: PathBuf::from("."),
+            generation: GenerationParams {
+                sparse_bias: 0.0,
+                self_modify: false,
+                symmetry: TableSymmetry::None,
+                start: StartState::Fixed(0),
+                layers: 1,
+                sensing: false,
+            },
+            rewind_snapshots: 60,
+            rewind_interval: 30,
+            mode: Mode::Plane,
+            trace_first: None,
+            trace_rows: 1_000_000,
+            stamp_milestones: Vec::new(),
+            decay: Decay::None,
+            paths_svg: None,
+            paths_max_points: 200_000,
+            target_fps: None,
+            min_steps: 1,
+            max_steps: 1_000_000,
+            gravity_probability: 0.0,
+            gravity_interval: 1,
+            splat_size: None,
+            splat_strength: 0.5,
+            steps_per_frame: None,
+            scale: 1,
+            palette: Palette::Classic,
+            width: DEFAULT_WIDTH,
+            height: DEFAULT_HEIGHT,
+            headless: None,
+            warmup: 0,
+            seed: None,
+            load: None,
+            stats_every: 60,
+            verbose_stats: false,
+            frame_log: false,
+            stats_file: None,
+            tournament: None,
+            tournament_steps: 200_000,
+            fitness: Fitness::Coverage,
+            machine: None,
+        };
+
+        let mut args = env::args().skip(1);
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--screenshot-dir" => {
+                    options.screenshot_dir = PathBuf::from(args.next().expect("--screenshot-dir needs a path"));
+                }
+                "--sparse-bias" => {
+                    let bias = args.next().expect("--sparse-bias needs a probability");
+                    options.generation.sparse_bias =
+                        f64::from_str(&bias).expect("--sparse-bias is not a number");
+                    assert!(
+                        (0.0..=1.0).contains(&options.generation.sparse_bias),
+                        "--sparse-bias must be between 0 and 1"
+                    );
+                }
+                "--rewind-snapshots" => {
+                    let snapshots = args.next().expect("--rewind-snapshots needs a count");
+                    options.rewind_snapshots =
+                        usize::from_str(&snapshots).expect("--rewind-snapshots is not a number");
+                }
+                "--rewind-interval" => {
+                    let interval = args.next().expect("--rewind-interval needs a frame count");
+                    options.rewind_interval =
+                        u32::from_str(&interval).expect("--rewind-interval is not a number");
+                    assert!(options.rewind_interval >= 1, "--rewind-interval must be at least 1");
+                }
+                "--self-modify" => options.generation.self_modify = true,
+                "--sensing" => options.generation.sensing = true,
+                "--start-state" => {
+                    options.generation.start = match args.next().as_deref() {
+                        Some("random") => StartState::Random,
+                        Some("weighted") => StartState::Weighted,
+                        Some(state) => StartState::Fixed(
+                            u8::from_str(state).expect("--start-state needs a state number, random or weighted"),
+                        ),
+                        None => panic!("--start-state needs a state number, random or weighted"),
+                    };
+                }
+                "--one-d" => {
+                    assert!(options.mode != Mode::Hex, "--one-d can't be combined with a hex topology");
+                    options.mode = Mode::Line;
+                }
+                "--topology" => {
+                    assert!(options.mode != Mode::Line, "--topology can't be combined with --one-d");
+                    options.mode = match args.next().as_deref() {
+                        Some("square") => Mode::Plane,
+                        Some("hex") => Mode::Hex,
+                        _ => panic!("--topology needs one of square, hex"),
+                    };
+                }
+                "--layers" => {
+                    let layers = args.next().expect("--layers needs a layer count");
+                    options.generation.layers = u8::from_str(&layers).expect("--layers is not a number");
+                }
+                "--symmetry" => {
+                    options.generation.symmetry = match args.next().as_deref() {
+                        Some("none") => TableSymmetry::None,
+                        Some("h") => TableSymmetry::H,
+                        Some("v") => TableSymmetry::V,
+                        Some("hv") => TableSymmetry::HV,
+                        _ => panic!("--symmetry needs one of none, h, v, hv"),
+                    };
+                }
+                "--trace-first" => {
+                    options.trace_first = Some(PathBuf::from(args.next().expect("--trace-first needs a path")));
+                }
+                "--trace-rows" => {
+                    let rows = args.next().expect("--trace-rows needs a row count");
+                    options.trace_rows = u64::from_str(&rows).expect("--trace-rows is not a number");
+                }
+                "--stamp-milestones" => {
+                    let milestones = args.next().expect("--stamp-milestones needs a list of iterations");
+                    options.stamp_milestones = milestones
+                        .split(',')
+                        .map(|m| u32::from_str(m).expect("--stamp-milestones is not a list of numbers"))
+                        .collect();
+                }
+                "--decay" => {
+                    let usage = "--decay needs none, linear:<amount>:<every>, halve:<every> or threshold:<below>:<to>";
+                    options.decay = Decay::from_spec(&args.next().expect(usage)).expect(usage);
+                }
+                "--paths-svg" => {
+                    options.paths_svg = Some(PathBuf::from(args.next().expect("--paths-svg needs a path")));
+                }
+                "--paths-max-points" => {
+                    let points = args.next().expect("--paths-max-points needs a point count");
+                    options.paths_max_points =
+                        usize::from_str(&points).expect("--paths-max-points is not a number");
+                    assert!(options.paths_max_points >= 2, "--paths-max-points must be at least 2");
+                }
+                "--target-fps" => {
+                    let fps = args.next().expect("--target-fps needs a frame rate");
+                    let fps = f64::from_str(&fps).expect("--target-fps is not a number");
+                    assert!(fps > 0.0, "--target-fps must be positive");
+                    options.target_fps = Some(fps);
+                }
+                "--min-steps" => {
+                    let steps = args.next().expect("--min-steps needs a step count");
+                    options.min_steps = u32::from_str(&steps).expect("--min-steps is not a number");
+                    assert!(options.min_steps >= 1, "--min-steps must be at least 1");
+                }
+                "--max-steps" => {
+                    let steps = args.next().expect("--max-steps needs a step count");
+                    options.max_steps = u32::from_str(&steps).expect("--max-steps is not a number");
+                }
+                "--gravity" => {
+                    let probability = args.next().expect("--gravity needs a probability");
+                    options.gravity_probability = f64::from_str(&probability).expect("--gravity is not a number");
+                    assert!(
+                        (0.0..=1.0).contains(&options.gravity_probability),
+                        "--gravity must be between 0 and 1"
+                    );
+                }
+                "--gravity-interval" => {
+                    let steps = args.next().expect("--gravity-interval needs a step count");
+                    options.gravity_interval = u32::from_str(&steps).expect("--gravity-interval is not a number");
+                    assert!(options.gravity_interval >= 1, "--gravity-interval must be at least 1");
+                }
+                "--splat" => {
+                    let size = args.next().expect("--splat needs a kernel size");
+                    options.splat_size = Some(usize::from_str(&size).expect("--splat is not a number"));
+                }
+                "--splat-strength" => {
+                    let strength = args.next().expect("--splat-strength needs a strength");
+                    options.splat_strength = f64::from_str(&strength).expect("--splat-strength is not a number");
+                }
+                "--steps-per-frame" => {
+                    let steps = args.next().expect("--steps-per-frame needs a step count");
+                    options.steps_per_frame = Some(u32::from_str(&steps).expect("--steps-per-frame is not a number"));
+                }
+                "--scale" => {
+                    let scale = args.next().expect("--scale needs a factor");
+                    options.scale = usize::from_str(&scale).expect("--scale is not a number");
+                    assert!(options.scale >= 1, "--scale must be at least 1");
+                }
+                "--palette-preset" => {
+                    let name = args.next().expect("--palette-preset needs a preset name");
+                    options.palette = Palette::from_name(&name)
+                        .expect("--palette-preset needs one of classic, viridis, magma, cmyk, contrast, rainbow, grayscale, gradient");
+                }
+                "--width" => {
+                    let width = args.next().expect("--width needs a cell count");
+                    options.width = usize::from_str(&width).expect("--width is not a number");
+                    assert!(options.width >= 2, "--width must be at least 2");
+                }
+                "--height" => {
+                    let height = args.next().expect("--height needs a cell count");
+                    options.height = usize::from_str(&height).expect("--height is not a number");
+                    assert!(options.height >= 2, "--height must be at least 2");
+                }
+                "--headless" => {
+                    let steps = args.next().expect("--headless needs a step count");
+                    options.headless = Some(u32::from_str(&steps).expect("--headless is not a number"));
+                }
+                "--warmup" => {
+                    let steps = args.next().expect("--warmup needs a step count");
+                    options.warmup = u32::from_str(&steps).expect("--warmup is not a number");
+                }
+                "--stats-every" => {
+                    let frames = args.next().expect("--stats-every needs a frame count");
+                    options.stats_every = u32::from_str(&frames).expect("--stats-every is not a number");
+                    assert!(options.stats_every >= 1, "--stats-every must be at least 1");
+                }
+                "--verbose-stats" => options.verbose_stats = true,
+                "--frame-log" => options.frame_log = true,
+                "--stats-file" => {
+                    options.stats_file = Some(PathBuf::from(args.next().expect("--stats-file needs a path")));
+                }
+                "--tournament" => {
+                    let candidates = args.next().expect("--tournament needs a machine count");
+                    let candidates = u32::from_str(&candidates).expect("--tournament is not a number");
+                    assert!(candidates >= 1, "--tournament needs at least 1 machine");
+                    options.tournament = Some(candidates);
+                }
+                "--tournament-steps" => {
+                    let steps = args.next().expect("--tournament-steps needs a step count");
+                    options.tournament_steps = u32::from_str(&steps).expect("--tournament-steps is not a number");
+                }
+                "--fitness" => {
+                    let name = args.next().expect("--fitness needs a name");
+                    options.fitness =
+                        Fitness::from_name(&name).expect("--fitness needs one of coverage, entropy, bbox");
+                }
+                "--seed" => {
+                    let seed = args.next().expect("--seed needs a number");
+                    options.seed = Some(u64::from_str(&seed).expect("--seed is not a number"));
+                }
+                "--load" => {
+                    options.load = Some(PathBuf::from(args.next().expect("--load needs a path")));
+                }
+                "--machine" => {
+                    let path = PathBuf::from(args.next().expect("--machine needs a path"));
+                    if let Some(first) = &options.machine {
+                        eprintln!(
+                            "--machine can only be given once, since one machine runs at a time (got {} and {})",
+                            first.display(),
+                            path.display()
+                        );
+                        std::process::exit(1);
+                    }
+                    options.machine = Some(path);
+                }
+                _ => panic!("unknown argument {}", arg),
+            }
         }
+
+        options
     }
 }
 
-struct Transition {
-    state: u8,
-    symbol: u8,
-    action: Action,
+/// Picks a file name for a screenshot of `machine` inside `dir`, `art_<seed>_<iter>_<unixsecs>`
+/// for the run's `seed`. A nonempty `tag`, like the palette or "raw", is added so different
+/// renderings can be told apart. If that file already exists (two captures in the same second)
+/// a counter is appended until the name is free. Every capture, the S key and `--headless`
+/// alike, is named here.
+fn screenshot_path(dir: &Path, machine: &TuringMachine, seed: u64, tag: &str) -> PathBuf {
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("time went backwards")
+        .as_secs();
+    let mut stem = format!("art_{}_{}_{}", seed, machine.itr_count, timestamp);
+    if !tag.is_empty() {
+        stem = format!("{}_{}", stem, tag);
+    }
+
+    let mut path = dir.join(format!("{}.png", stem));
+    let mut counter = 1;
+    while path.exists() {
+        path = dir.join(format!("{}_{}.png", stem, counter));
+        counter += 1;
+    }
+    path
 }
 
-struct TuringMachine {
-    table: ArrayVec<[Transition; 4096]>,
-    map: [u8; WIDTH * HEIGHT],
-    num_states: u16,
-    num_symbols: u16,
-    state: u8,
-    xpos: usize,
-    ypos: usize,
-    itr_count: u32,
+/// Stops `recording` once its frames are written and says how it went.
+fn report_recording(recording: Recorder) {
+    let summary = recording.finish();
+    if let Some(error) = summary.last_error() {
+        eprintln!("{}", error);
+    }
+    println!("{}", summary);
 }
 
-/*
-N states, one start state
-K symbols
-4 actions (left, right up, down)
-N x K -> N x K x A
-*/
-
-impl TuringMachine {
-    fn new(num_states: u16, num_symbols: u16) -> TuringMachine {
-        assert!(num_states >= 1, "must have at least 1 state");
-        assert!(num_symbols >= 2, "must have at least 2 symbols");
-        assert!(
-            num_states * num_symbols <= 4096,
-            "num_states * num_symbols <= 4096"
-        );
+/// Prints the share of each action executed since the last report and starts a new count.
+/// Actions that never ran are left out.
+fn report_action_profile(machine: &mut TuringMachine) {
+    let total: u64 = machine.action_counts.iter().map(|&n| n as u64).sum();
+    if total == 0 {
+        return;
+    }
 
-        let mut table = ArrayVec::new();
-        let mut rng = SmallRng::from_entropy();
-        for _ in 0..(num_states * num_symbols) {
-            let trans = Transition {
-                state: rng.gen_range(0, num_states) as u8,
-                symbol: rng.gen_range(0, num_symbols) as u8,
-                action: rng.gen(),
-            };
+    let shares: Vec<String> = ACTIONS
+        .iter()
+        .zip(machine.action_counts.iter())
+        .filter(|&(_, &count)| count > 0)
+        .map(|(action, &count)| format!("{} {:.1}%", action.name(), 100.0 * count as f64 / total as f64))
+        .collect();
+    println!("actions {}", shares.join(" "));
+    machine.action_counts = [0; ACTIONS.len()];
+}
+
+/// Stops tracing, if a trace is running, and records `reason` as its last row.
+fn end_trace(trace: &mut Option<Trace>, machine: &TuringMachine, reason: &str) {
+    if let Some(trace) = trace.take() {
+        if let Err(error) = trace.finish(machine, reason) {
+            eprintln!("could not finish trace: {}", error);
+        }
+    }
+}
+
+/// Fills the HUD in the corner of the window with the frame rate, iteration count and speed, or
+/// clears it when it is hidden. The text sits on the palette's darkest symbol.
+fn update_hud(
+    screen: &mut Screen,
+    show: bool,
+    machine: &TuringMachine,
+    palette: Palette,
+    fps: f64,
+    steps_per_frame: u32,
+    playing: bool,
+) {
+    screen.hud.clear();
+    if !show {
+        return;
+    }
+    screen.hud.push(format!("fps {:.1}", fps));
+    screen.hud.push(format!("iteration {}", machine.itr_count));
+    screen.hud.push(if playing {
+        format!("steps/frame {}", steps_per_frame)
+    } else {
+        "paused".to_string()
+    });
+    screen.hud_paper = palette.darkest(machine.num_symbols);
+}
 
-            table.push(trans);
+/// Everything besides the table that changes the map or the head during a frame.
+struct Effects {
+    gravity: Option<Gravity>,
+    splat: Option<Splat>,
+    milestones: Vec<u32>,
+    decay: Decay,
+}
+
+/// Runs one frame's worth of steps. While the machine is traced or its path recorded it is
+/// stepped one iteration at a time so every move is seen.
+fn step_frame(
+    machine: &mut TuringMachine,
+    num_iters: u32,
+    trace: &mut Option<Trace>,
+    paths: &mut Option<PathRecorder>,
+    gravity: &mut Option<Gravity>,
+    splat: &Option<Splat>,
+) {
+    if trace.is_none() && paths.is_none() && gravity.is_none() && splat.is_none() {
+        machine.step(num_iters);
+        return;
+    }
+
+    for _ in 0..num_iters {
+        if let Some(gravity) = gravity {
+            machine.pull = gravity.pull(machine);
+        }
+        let before = trace.as_ref().map(|_| Before::capture(machine));
+        let (x, y) = (machine.xpos, machine.ypos);
+        let action = machine.step_once();
+        if let Some(splat) = splat {
+            splat.apply(machine, x, y);
         }
 
-        TuringMachine {
-            table,
-            map: [0u8; WIDTH * HEIGHT],
-            num_states,
-            num_symbols,
-            state: 0,
-            xpos: 0,
-            ypos: 0,
-            itr_count: 0,
+        if let (Some(recording), Some(before)) = (trace.as_mut(), before) {
+            if let Err(error) = recording.record(&before, action, machine) {
+                eprintln!("could not write trace: {}", error);
+                *trace = None;
+            } else if recording.is_full() {
+                end_trace(trace, machine, "row cap reached");
+            }
+        }
+        if let Some(paths) = paths {
+            paths.record(machine.xpos, machine.ypos);
         }
     }
+}
 
-    fn from_string(transition_hash: &str) -> TuringMachine {
-        let mut trans_table = transition_hash.split(",").map(|n| u8::from_str(n).expect("not parsable"));
-        let num_states = trans_table.next().unwrap() as u16;
-        let num_symbols = trans_table.next().unwrap() as u16;
+/// A genome given on the command line is either a machine string as accepted by `from_string`
+/// or a file containing one.
+fn read_genome(genome: String) -> String {
+    let genome = if Path::new(&genome).is_file() {
+        fs::read_to_string(&genome).expect("could not read genome file")
+    } else {
+        genome
+    };
+    genome.trim().to_string()
+}
 
-        let mut table = ArrayVec::new();
-        for _ in 0..(num_states * num_symbols) {
-            let state = trans_table.next().unwrap();
-            let symbol = trans_table.next().unwrap();
+/// `art genome-image <genome> <out.png>` renders a transition table to a PNG.
+fn genome_image_command(mut args: impl Iterator<Item = String>) {
+    let usage = "usage: art genome-image <genome> <out.png>";
+    let genome = read_genome(args.next().expect(usage));
+    let out = args.next().expect(usage);
 
-            let action = match trans_table.next().unwrap() {
-                0 => Action::Left,
-                1 => Action::Right,
-                2 => Action::Up,
-                3 => Action::Down,
-                _ => panic!("no such action"),
-            };
+    let machine = parse_genome(&genome, DEFAULT_WIDTH, DEFAULT_HEIGHT);
 
-            let trans = Transition {
-                state,
-                symbol,
-                action,
-            };
+    genome_image::render(&machine)
+        .save(&out)
+        .expect("could not write genome image");
+    println!("wrote {}", out);
+}
 
-            table.push(trans);
+/// `art to-json <genome> [out.json]` converts a machine string into a JSON definition, written
+/// to `out.json` or printed if no path is given.
+fn to_json_command(mut args: impl Iterator<Item = String>) {
+    let usage = "usage: art to-json <genome> [out.json]";
+    let genome = read_genome(args.next().expect(usage));
+    let machine = parse_genome(&genome, DEFAULT_WIDTH, DEFAULT_HEIGHT);
+    match args.next() {
+        Some(out) => {
+            fs::write(&out, machine.to_json() + "\n").expect("could not write JSON file");
+            println!("wrote {}", out);
         }
+        None => println!("{}", machine.to_json()),
+    }
+}
+
+/// Parses a machine string given on the command line, exiting with the reason if it is wrong.
+fn parse_genome(genome: &str, width: usize, height: usize) -> TuringMachine {
+    TuringMachine::from_string(genome, width, height).unwrap_or_else(|error| {
+        eprintln!("could not parse machine string: {}", error);
+        std::process::exit(1);
+    })
+}
 
-        TuringMachine {
-            table,
-            map: [0u8; WIDTH * HEIGHT],
-            num_states,
-            num_symbols,
-            state: 0,
-            xpos: 0,
-            ypos: 0,
-            itr_count: 0,
+/// Reads the JSON machine definition at `path`, exiting with the reason if it can't be used.
+fn read_machine(path: &Path, width: usize, height: usize) -> TuringMachine {
+    let machine = fs::read_to_string(path)
+        .map_err(|error| error.to_string())
+        .and_then(|json| TuringMachine::from_json(&json, width, height));
+    match machine {
+        Ok(machine) => machine,
+        Err(error) => {
+            eprintln!("could not load {}: {}", path.display(), error);
+            std::process::exit(1);
         }
     }
+}
 
-    fn reset(&mut self) {
-        self.state = 0;
-        self.ypos = 0;
-        self.xpos = 0;
-        self.itr_count = 0;
+/// `art preview <genome> [--iters N] [--size N] [--out path]` runs the genome on a blank
+/// `size` x `size` map and writes the result as a PNG in the classic palette. A parsed machine
+/// starts in state 0 at the corner and stepping it never draws random numbers, so the same
+/// inputs always give the same image.
+fn preview_command(mut args: impl Iterator<Item = String>) {
+    let usage = "usage: art preview <genome> [--iters N] [--size N] [--out path]";
+    let genome = read_genome(args.next().expect(usage));
+    let mut iters: u32 = 20_000;
+    let mut size: usize = 256;
+    let mut out = PathBuf::from("preview.png");
 
-        self.map = [0u8; WIDTH * HEIGHT];
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--iters" => {
+                let count = args.next().expect("--iters needs an iteration count");
+                iters = u32::from_str(&count).expect("--iters is not a number");
+            }
+            "--size" => {
+                let cells = args.next().expect("--size needs a cell count");
+                size = usize::from_str(&cells).expect("--size is not a number");
+            }
+            "--out" => out = PathBuf::from(args.next().expect("--out needs a path")),
+            _ => panic!("{}", usage),
+        }
     }
 
-    fn update(&mut self, num_iters: u32) {
-        for _ in 0..num_iters {
-            let symbol = &mut self.map[WIDTH * self.ypos + self.xpos];
+    preview(parse_genome(&genome, size, size), iters)
+        .save(&out)
+        .expect("could not write preview");
+    println!("wrote {}", out.display());
+}
 
-            let trans = &self.table[(self.num_states as u8 * (*symbol) + self.state) as usize];
-            self.state = trans.state;
+/// Steps `machine` `iters` times and colors the map in the classic palette.
+fn preview(mut machine: TuringMachine, iters: u32) -> RgbImage {
+    machine.step(iters);
+    let mut display = Vec::new();
+    Palette::Classic.image(machine.display(&mut display), machine.width, machine.height, machine.num_symbols)
+}
 
-            *symbol = trans.symbol;
+/// Runs one frame's worth of steps: gravity is refreshed, the machine stepped, the glyph
+/// stamped for every milestone passed during the frame and the map decayed. The live loop,
+/// `--warmup` and `--headless` all step through here so they end up with the same map.
+fn advance_frame(
+    machine: &mut TuringMachine,
+    num_iters: u32,
+    trace: &mut Option<Trace>,
+    paths: &mut Option<PathRecorder>,
+    effects: &mut Effects,
+) {
+    let age = machine.itr_count;
+    if let Some(gravity) = &mut effects.gravity {
+        gravity.refresh(machine);
+    }
+    step_frame(machine, num_iters, trace, paths, &mut effects.gravity, &effects.splat);
+    for &milestone in &effects.milestones {
+        if age < milestone && milestone <= machine.itr_count {
+            signature::stamp(machine);
+        }
+    }
+    effects.decay.apply(machine, age);
+}
 
-            match trans.action {
-                Action::Left => {
-                    self.xpos += 1;
-                    if self.xpos >= WIDTH {
-                        self.xpos -= WIDTH;
-                    }
-                }
-                Action::Right => {
-                    self.xpos = if let Some(x) = self.xpos.checked_sub(1) {
-                        x
-                    } else {
-                        WIDTH - 1
-                    };
-                }
-                Action::Up => {
-                    self.ypos = if let Some(y) = self.ypos.checked_sub(1) {
-                        y
-                    } else {
-                        HEIGHT - 1
-                    };
+/// Steps `machine` through `steps` iterations before anything is shown, in frames of
+/// `steps_per_frame` like the live loop, optionally drawing a progress bar on stderr.
+fn skip_ahead(
+    machine: &mut TuringMachine,
+    steps: u32,
+    steps_per_frame: u32,
+    effects: &mut Effects,
+    progress: bool,
+) {
+    const BAR: usize = 40;
 
-                }
-                Action::Down => {
-                    self.ypos += 1;
-                    if self.ypos >= HEIGHT {
-                        self.ypos -= HEIGHT;
-                    }
-                }
-            }
-            self.itr_count += 1;
+    let mut done = 0;
+    while done < steps {
+        let chunk = (steps - done).min(steps_per_frame.max(1));
+        advance_frame(machine, chunk, &mut None, &mut None, effects);
+        done += chunk;
+
+        if progress {
+            let filled = (BAR as u64 * done as u64 / steps as u64) as usize;
+            eprint!(
+                "\rwarmup [{}{}] {}/{}",
+                "#".repeat(filled),
+                " ".repeat(BAR - filled),
+                done,
+                steps
+            );
         }
     }
+    if progress {
+        eprintln!();
+    }
+}
+
+/// `--tournament <n>` generates `n` random machines, runs each alone on a blank map for
+/// `--tournament-steps` steps and ranks them by `--fitness`. The ranking is printed with every
+/// machine string so the losers can be looked at too, and the winner comes back on a blank map.
+/// Only one machine runs at a time, so the window starts with the winner alone rather than the
+/// top k; any of the others can be run afterwards with `spawn <string>` on the REPL.
+fn run_tournament(candidates: u32, options: &Options, rng: &mut SmallRng) -> TuringMachine {
+    let (width, height) = (options.width, options.height);
+    let mut ranked: Vec<(f64, String, u8)> = (0..candidates)
+        .map(|_| {
+            let mut machine = TuringMachine::new(12, 7, options.generation, options.mode, width, height, rng);
+            // Self-modifying tables change as they run, so the string is taken up front.
+            let genome = machine.to_string();
+            let start = machine.state;
+            machine.step(options.tournament_steps);
+            (options.fitness.score(&machine), genome, start)
+        })
+        .collect();
+    ranked.sort_by(|a, b| b.0.total_cmp(&a.0));
+
+    println!("rank {} machine", options.fitness.name());
+    for (rank, (score, genome, _)) in ranked.iter().enumerate() {
+        println!("{:>4} {:.3} {}", rank + 1, score, genome);
+    }
+
+    let (_, genome, start) = &ranked[0];
+    let mut best = TuringMachine::from_string(genome, width, height).expect("written machine strings parse");
+    best.state = *start;
+    best
+}
+
+/// Tag for images in `palette`; the classic palette goes untagged.
+fn palette_tag(palette: Palette) -> &'static str {
+    if palette == Palette::Classic {
+        ""
+    } else {
+        palette.name()
+    }
+}
+
+/// Saves the map at one pixel per cell to the screenshot directory, whatever the window size or
+/// scale, and returns where it went. The map is drawn on the CPU with `shading`, so the palette,
+/// symmetry, style and layer shading are those on screen; `raw` saves the symbols themselves as
+/// gray levels instead. Either way the palette and symmetry are noted in the PNG's metadata.
+fn save_screenshot(
+    machine: &TuringMachine,
+    dir: &Path,
+    seed: u64,
+    shading: &Shading,
+    raw: bool,
+) -> io::Result<PathBuf> {
+    let mut display = Vec::new();
+    let symbols = machine.display(&mut display);
+    let (width, height) = (machine.width, machine.height);
+    let text = [
+        ("Palette", shading.palette.name().to_string()),
+        ("Symmetry", shading.symmetry.name().to_string()),
+    ];
+    if raw {
+        let path = screenshot_path(dir, machine, seed, "raw");
+        metadata::save_gray(&palette::symbols_image(symbols, width, height), &path, &text)?;
+        Ok(path)
+    } else {
+        // Layer 1 goes next to each cell like in the window buffer.
+        let (pixels, channels) = match machine.layers.first() {
+            Some(layer) => (symbols.iter().zip(layer).flat_map(|(&symbol, &shade)| [symbol, shade]).collect(), 2),
+            None => (symbols.to_vec(), 1),
+        };
+        let path = screenshot_path(dir, machine, seed, palette_tag(shading.palette));
+        metadata::save_rgb(&shading.image(&pixels, channels, width, height), &path, &text)?;
+        Ok(path)
+    }
+}
+
+/// `--headless <steps>` runs the machine without opening a window, then saves the map in the
+/// palette's colors to the screenshot directory and prints the machine string so the result can
+/// be reproduced. It is drawn in the plain style without symmetry, as a new window starts out.
+fn run_headless(machine: &TuringMachine, options: &Options, seed: u64, palette: Palette) {
+    let mut shading = Shading::new(machine.mode, machine.width, machine.height, machine.num_symbols);
+    shading.palette = palette;
+    let path = save_screenshot(machine, &options.screenshot_dir, seed, &shading, false)
+        .expect("could not write headless image");
+    println!("wrote {}", path.display());
+    println!("machine {}", machine);
 }
 
 fn main() {
-    let mut fb = mini_gl_fb::gotta_go_fast("art", WIDTH as f64, HEIGHT as f64);
+    if env::args().nth(1).as_deref() == Some("genome-image") {
+        genome_image_command(env::args().skip(2));
+        return;
+    }
+    if env::args().nth(1).as_deref() == Some("to-json") {
+        to_json_command(env::args().skip(2));
+        return;
+    }
+    if env::args().nth(1).as_deref() == Some("preview") {
+        preview_command(env::args().skip(2));
+        return;
+    }
 
-    fb.change_buffer_format::<u8>(BufferFormat::R);
-    fb.use_post_process_shader(COLOR_SYMBOLS);
+    let options = Options::from_args();
+    assert!(
+        options.scale == 1 || options.mode != Mode::Hex,
+        "--scale can't be combined with a hex topology"
+    );
+    fs::create_dir_all(&options.screenshot_dir).expect("could not create screenshot directory");
+
+    let (width, height) = (options.width, options.height);
+    let mode = options.mode;
+    let seed = options.seed.unwrap_or_else(|| SmallRng::from_entropy().gen());
+    println!("seed {}", seed);
+    let mut rng = SmallRng::seed_from_u64(seed);
 
     //let mut machine = TuringMachine::from_string("5,4,4,2,1,1,3,2,4,3,1,2,2,3,1,2,1,3,2,0,2,2,3,2,3,0,2,3,2,4,2,2,0,2,0,1,1,0,2,3,0,1,2,1,2,3,3,3,2,0,1,1,3,2,2,0,2,2,3,3,2,0");
-    let mut machine = TuringMachine::from_string("3,6,2,2,3,2,4,0,0,1,0,2,1,2,1,1,0,1,2,3,2,3,0,2,1,0,2,5,3,2,5,2,2,4,1,1,5,0,2,4,3,0,4,0,0,1,1,2,1,3,2,1,0,2,2,0");
+    let save_path = options
+        .load
+        .clone()
+        .unwrap_or_else(|| options.screenshot_dir.join("art.save"));
+    let mut machine = if let Some(path) = &options.load {
+        assert!(options.tournament.is_none(), "--tournament can't be combined with --load");
+        assert!(options.machine.is_none(), "--machine can't be combined with --load");
+        let machine = save::read(path, width, height).expect("could not load save file");
+        assert!(machine.mode == mode, "the save file is for a different topology");
+        machine
+    } else if let Some(path) = &options.machine {
+        assert!(options.tournament.is_none(), "--tournament can't be combined with --machine");
+        let machine = read_machine(path, width, height);
+        assert!(machine.mode == mode, "the machine file is for a different topology");
+        machine
+    } else if let Some(candidates) = options.tournament {
+        run_tournament(candidates, &options, &mut rng)
+    } else {
+        match mode {
+            Mode::Plane if options.generation.layers > 1 || options.generation.sensing => {
+                TuringMachine::new(12, 7, options.generation, mode, width, height, &mut rng)
+            }
+            Mode::Plane => TuringMachine::from_string("3,6,2,2,3,2,4,0,0,1,0,2,1,2,1,1,0,1,2,3,2,3,0,2,1,0,2,5,3,2,5,2,2,4,1,1,5,0,2,4,3,0,4,0,0,1,1,2,1,3,2,1,0,2,2,0", width, height)
+                .expect("the built in machine parses"),
+            Mode::Line | Mode::Hex => TuringMachine::new(12, 7, options.generation, mode, width, height, &mut rng),
+        }
+    };
+
+    let gravity = if options.gravity_probability > 0.0 {
+        assert!(mode == Mode::Plane, "--gravity only works on the square plane");
+        Some(Gravity::new(options.gravity_probability, options.gravity_interval, rng.gen()))
+    } else {
+        None
+    };
+
+    let splat = options.splat_size.map(|size| {
+        assert!(mode == Mode::Plane, "--splat only works on the square plane");
+        Splat::new(size, options.splat_strength)
+    });
+    let mut effects = Effects {
+        gravity,
+        splat,
+        milestones: options.stamp_milestones.clone(),
+        decay: options.decay,
+    };
+
+    let mut steps_per_frame = options.steps_per_frame.unwrap_or(match mode {
+        Mode::Plane | Mode::Hex => STEPS_PER_FRAME,
+        Mode::Line => LINE_STEPS_PER_FRAME,
+    });
+
+    if let Some(steps) = options.headless {
+        skip_ahead(
+            &mut machine,
+            steps,
+            steps_per_frame,
+            &mut effects,
+            false,
+        );
+        run_headless(&machine, &options, seed, options.palette);
+        return;
+    }
+    if options.warmup > 0 {
+        skip_ahead(
+            &mut machine,
+            options.warmup,
+            steps_per_frame,
+            &mut effects,
+            true,
+        );
+    }
+
+    // Layer 1 goes into a second channel of the buffer, which the shader shades colors by.
+    let layered = machine.num_layers() > 1 || options.generation.layers > 1;
+    let mut screen = Screen::new(options.scale, width, height, if layered { 2 } else { 1 });
+    let mut fb = mini_gl_fb::gotta_go_fast("art", screen.width() as f64, screen.height() as f64);
+
+    fb.change_buffer_format::<u8>(if layered { BufferFormat::RG } else { BufferFormat::R });
+
+    let mut shading = Shading::new(mode, width, height, machine.num_symbols);
+    shading.palette = options.palette;
+    fb.use_post_process_shader(&shading.shader());
+
+    let mut speed = options
+        .target_fps
+        .map(|fps| SpeedController::new(fps, options.min_steps, options.max_steps));
+
+    let mut rewind = Rewind::new(options.rewind_snapshots, options.rewind_interval);
+    let mut generation = options.generation;
+    let commands = repl::spawn_reader();
+
+    let mut trace = options.trace_first.as_ref().map(|path| {
+        Trace::create(path, options.trace_rows).expect("could not create trace file")
+    });
+    let mut paths = options
+        .paths_svg
+        .as_ref()
+        .map(|_| PathRecorder::new(options.paths_max_points, width, height));
+
+    let mut stats_file = options.stats_file.as_ref().map(|path| {
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .expect("could not open stats file");
+        if file.metadata().expect("could not read stats file").len() == 0 {
+            writeln!(file, "{}", Stats::CSV_HEADER).expect("could not write stats file");
+        }
+        file
+    });
+    let mut stats_frames = 0;
 
     let mut previous = SystemTime::now();
+    let mut profile_started = SystemTime::now();
 
     let mut playing = true;
     let mut space_pressed = false;
     let mut s_pressed = false;
+    let mut p_pressed = false;
+    let mut k_pressed = false;
+    let mut c_pressed = false;
+    let mut v_pressed = false;
+    let mut digit_pressed = false;
+    let mut bracket_pressed = false;
+    let mut b_pressed = false;
+    let mut f_pressed = false;
+    let mut n_pressed = false;
+    let mut speed_pressed = false;
+    let mut click_pressed = false;
+    let mut m_pressed = false;
+    let mut save_pressed = false;
+    let mut d_pressed = false;
+    let mut h_pressed = false;
+    let mut brush_pressed = false;
+    let mut tab_pressed = false;
+    let mut escape_pressed = false;
+    let mut view_pressed = false;
+    // The map cell under the cursor when a middle button drag started.
+    let mut drag_anchor: Option<(f64, f64)> = None;
+    let mut show_hud = false;
+    let mut fps = 0.0;
+    let mut brush = Brush::new();
+    // Where the last painted frame left the cursor, in map cells, while a stroke is going.
+    let mut brush_last: Option<(f64, f64)> = None;
+    let mut recorder: Option<Recorder> = None;
 
     fb.glutin_handle_basic_input(|fb, input| {
         let elapsed = previous.elapsed().unwrap();
         let seconds = elapsed.as_secs() as f64 + elapsed.subsec_nanos() as f64 * 1e-9;
 
+        // Escape lets go of an inspected machine first and quits once nothing is inspected.
         if input.key_is_down(VirtualKeyCode::Escape) {
-            return false;
+            if !escape_pressed {
+                if !screen.inspected {
+                    return false;
+                }
+                screen.inspected = false;
+                fb.update_buffer(screen.frame(&machine));
+                escape_pressed = true;
+            }
+        } else {
+            escape_pressed = false;
+        }
+
+        while let Ok(command) = commands.try_recv() {
+            match command {
+                Command::Pause => playing = false,
+                Command::Play => playing = true,
+                Command::Step(count) => {
+                    if rewind.is_rewinding() {
+                        rewind.resume();
+                    }
+                    advance_frame(&mut machine, count, &mut trace, &mut paths, &mut effects);
+                    rewind.record(&machine, &rng, effects.gravity.as_ref());
+                    fb.update_buffer(screen.frame(&machine));
+                }
+                Command::Reset => {
+                    machine.reset();
+                    rewind.clear();
+                }
+                Command::Set(Setting::StepsPerFrame(steps)) => steps_per_frame = steps,
+                Command::Set(Setting::SparseBias(bias)) => generation.sparse_bias = bias,
+                Command::Spawn(genome) => {
+                    match TuringMachine::from_string(&genome, width, height) {
+                        Ok(spawned) if spawned.mode != mode => {
+                            eprintln!("could not spawn: the machine is for a different topology")
+                        }
+                        Ok(spawned) => {
+                            end_trace(&mut trace, &machine, "replaced");
+                            machine = spawned;
+                            screen.inspected = false;
+                            rewind.clear();
+                        }
+                        Err(error) => eprintln!("could not spawn: {}", error),
+                    }
+                }
+                Command::Save(path) => match save::write(&machine, &path) {
+                    Ok(()) => println!("saved {}", path.display()),
+                    Err(error) => eprintln!("could not save {}: {}", path.display(), error),
+                },
+            }
         }
 
         if input.key_is_down(VirtualKeyCode::R) {
-            let mut rng = SmallRng::from_entropy();
             machine.reset();
-            machine.state = rng.gen_range(0, machine.num_states) as u8;
+            machine.state = machine.pick_start_state(StartState::Random, &mut rng);
+            rewind.clear();
         }
 
         if input.key_is_down(VirtualKeyCode::S) {
             if !s_pressed {
-                screenshot_window(format!("screenshot-{:?}.png", previous.duration_since(UNIX_EPOCH).expect("time went backwards")));
+                // Shift-S saves the raw symbols for coloring later.
+                match save_screenshot(&machine, &options.screenshot_dir, seed, &shading, input.modifiers.shift) {
+                    Ok(path) => println!("saved {}", path.display()),
+                    Err(error) => eprintln!("could not save screenshot: {}", error),
+                }
                 s_pressed = true;
             }
         } else {
             s_pressed = false
         }
 
+        if input.key_is_down(VirtualKeyCode::P) {
+            if !p_pressed {
+                println!("machine {}", machine);
+                p_pressed = true;
+            }
+        } else {
+            p_pressed = false;
+        }
+
+        if input.key_is_down(VirtualKeyCode::K) {
+            if !k_pressed {
+                shading.symmetry = shading.symmetry.next();
+                fb.use_post_process_shader(&shading.shader());
+                println!("symmetry {}", shading.symmetry.name());
+                k_pressed = true;
+            }
+        } else {
+            k_pressed = false;
+        }
+
+        if input.key_is_down(VirtualKeyCode::C) {
+            if !c_pressed {
+                shading.style = shading.style.next();
+                fb.use_post_process_shader(&shading.shader());
+                println!("style {}", shading.style.name());
+                c_pressed = true;
+            }
+        } else {
+            c_pressed = false;
+        }
+
+        if input.key_is_down(VirtualKeyCode::V) {
+            if !v_pressed {
+                shading.palette = shading.palette.next();
+                fb.use_post_process_shader(&shading.shader());
+                println!("palette {}", shading.palette.name());
+                v_pressed = true;
+            }
+        } else {
+            v_pressed = false;
+        }
+
+        // 1 to 8 pick a palette directly, in the order V cycles through them.
+        const DIGITS: [VirtualKeyCode; 8] = [
+            VirtualKeyCode::Key1,
+            VirtualKeyCode::Key2,
+            VirtualKeyCode::Key3,
+            VirtualKeyCode::Key4,
+            VirtualKeyCode::Key5,
+            VirtualKeyCode::Key6,
+            VirtualKeyCode::Key7,
+            VirtualKeyCode::Key8,
+        ];
+        if let Some(digit) = DIGITS.iter().position(|&key| input.key_is_down(key)) {
+            if !digit_pressed {
+                shading.palette = Palette::nth(digit).unwrap();
+                fb.use_post_process_shader(&shading.shader());
+                println!("palette {}", shading.palette.name());
+                digit_pressed = true;
+            }
+        } else {
+            digit_pressed = false;
+        }
+
+        // Ramp palettes are spread over the machine's symbols, so the shader is rebuilt when a
+        // machine with a different symbol count takes over.
+        if shading.num_symbols != machine.num_symbols {
+            shading.num_symbols = machine.num_symbols;
+            fb.use_post_process_shader(&shading.shader());
+        }
+
+        // M starts writing every played frame to a fresh directory as numbered PNGs and stops
+        // again on the next press.
+        if input.key_is_down(VirtualKeyCode::M) {
+            if !m_pressed {
+                if let Some(recording) = recorder.take() {
+                    report_recording(recording);
+                } else {
+                    let timestamp = SystemTime::now()
+                        .duration_since(UNIX_EPOCH)
+                        .expect("time went backwards")
+                        .as_secs();
+                    let dir = options.screenshot_dir.join(format!("recording-{}", timestamp));
+                    match Recorder::start(dir.clone(), screen.width(), screen.height(), screen.channels()) {
+                        Ok(recording) => {
+                            println!("recording to {}", dir.display());
+                            recorder = Some(recording);
+                        }
+                        Err(error) => eprintln!("could not start recording: {}", error),
+                    }
+                }
+                m_pressed = true;
+            }
+        } else {
+            m_pressed = false;
+        }
+
+        // H rings the head so it can be found on a busy map.
+        if input.key_is_down(VirtualKeyCode::H) {
+            if !h_pressed {
+                screen.head_marker = !screen.head_marker;
+                fb.update_buffer(screen.frame(&machine));
+                h_pressed = true;
+            }
+        } else {
+            h_pressed = false;
+        }
+
+        // Arrow keys pan the view by an eighth of the window, Page Up and Page Down zoom in
+        // and out and Home goes back to the whole map. The shader offsets alternate window rows
+        // on hex maps, so like --scale the view only works on the other topologies.
+        let view_keys = [
+            VirtualKeyCode::Left,
+            VirtualKeyCode::Right,
+            VirtualKeyCode::Up,
+            VirtualKeyCode::Down,
+            VirtualKeyCode::PageUp,
+            VirtualKeyCode::PageDown,
+            VirtualKeyCode::Home,
+        ];
+        let view_key = view_keys.iter().copied().find(|&key| input.key_is_down(key));
+        if let Some(key) = view_key.filter(|_| mode != Mode::Hex) {
+            if !view_pressed {
+                let step = (screen.visible_width() / 8).max(1) as isize;
+                match key {
+                    VirtualKeyCode::Left => screen.pan(-step, 0),
+                    VirtualKeyCode::Right => screen.pan(step, 0),
+                    VirtualKeyCode::Up => screen.pan(0, step),
+                    VirtualKeyCode::Down => screen.pan(0, -step),
+                    VirtualKeyCode::PageUp => screen.change_zoom(true),
+                    VirtualKeyCode::PageDown => screen.change_zoom(false),
+                    _ => screen.reset_view(),
+                }
+                if matches!(key, VirtualKeyCode::PageUp | VirtualKeyCode::PageDown) {
+                    println!("zoom {}x", screen.zoom());
+                }
+                fb.update_buffer(screen.frame(&machine));
+                view_pressed = true;
+            }
+        } else {
+            view_pressed = false;
+        }
+
+        // Dragging with the middle button pulls the map along with the cursor.
+        if input.mouse_is_down(MouseButton::Middle) && mode != Mode::Hex {
+            let (x, y) = input.mouse_pos;
+            let cursor = screen.cell_at(x, y);
+            let anchor = *drag_anchor.get_or_insert(cursor);
+            // The view wraps, so the cursor can land a whole map away from the anchor.
+            let dx = ((anchor.0 - cursor.0).round() as isize).rem_euclid(width as isize);
+            let dy = ((anchor.1 - cursor.1).round() as isize).rem_euclid(height as isize);
+            if dx != 0 || dy != 0 {
+                screen.pan(dx, dy);
+                fb.update_buffer(screen.frame(&machine));
+            }
+        } else {
+            drag_anchor = None;
+        }
+
+        // Tab shows and hides the HUD.
+        if input.key_is_down(VirtualKeyCode::Tab) {
+            if !tab_pressed {
+                show_hud = !show_hud;
+                update_hud(&mut screen, show_hud, &machine, shading.palette, fps, steps_per_frame, playing);
+                fb.update_buffer(screen.frame(&machine));
+                tab_pressed = true;
+            }
+        } else {
+            tab_pressed = false;
+        }
+
+        if input.key_is_down(VirtualKeyCode::D) {
+            if !d_pressed {
+                effects.decay = effects.decay.next();
+                println!("decay {}", effects.decay.name());
+                d_pressed = true;
+            }
+        } else {
+            d_pressed = false;
+        }
+
+        // F5 saves the run and F9 goes back to the last save.
+        let saving = input.key_is_down(VirtualKeyCode::F5);
+        if saving || input.key_is_down(VirtualKeyCode::F9) {
+            if !save_pressed {
+                if saving {
+                    match save::write(&machine, &save_path) {
+                        Ok(()) => println!("saved {}", save_path.display()),
+                        Err(error) => eprintln!("could not save {}: {}", save_path.display(), error),
+                    }
+                } else {
+                    match save::read(&save_path, width, height) {
+                        Ok(loaded) if loaded.mode != mode => {
+                            eprintln!("{} is for a different topology", save_path.display())
+                        }
+                        Ok(loaded) => {
+                            end_trace(&mut trace, &machine, "replaced");
+                            machine = loaded;
+                            screen.inspected = false;
+                            rewind.clear();
+                            fb.update_buffer(screen.frame(&machine));
+                            println!("loaded {}", save_path.display());
+                        }
+                        Err(error) => eprintln!("could not load {}: {}", save_path.display(), error),
+                    }
+                }
+                save_pressed = true;
+            }
+        } else {
+            save_pressed = false;
+        }
+
+        // [ and ] tweak the active style: CRT curvature or the number of dither levels.
+        let bracket_up = input.key_is_down(VirtualKeyCode::RBracket);
+        if bracket_up || input.key_is_down(VirtualKeyCode::LBracket) {
+            if !bracket_pressed {
+                shading.adjust(bracket_up);
+                fb.use_post_process_shader(&shading.shader());
+                println!(
+                    "curvature {} dither levels {}",
+                    shading.curvature, shading.dither_levels
+                );
+                bracket_pressed = true;
+            }
+        } else {
+            bracket_pressed = false;
+        }
+
+        // , and . shrink and grow the brush, ; and ' step the symbol it paints.
+        let grow = input.key_is_down(VirtualKeyCode::Period);
+        let brighter = input.key_is_down(VirtualKeyCode::Apostrophe);
+        let shrink = input.key_is_down(VirtualKeyCode::Comma);
+        if grow || shrink || brighter || input.key_is_down(VirtualKeyCode::Semicolon) {
+            if !brush_pressed {
+                if grow || shrink {
+                    brush.resize(grow);
+                } else {
+                    brush.adjust_value(brighter, machine.num_symbols);
+                }
+                println!(
+                    "brush radius {} value {}",
+                    brush.radius,
+                    brush.value.min(machine.num_symbols as u8 - 1)
+                );
+                brush_pressed = true;
+            }
+        } else {
+            brush_pressed = false;
+        }
+
+        // Ctrl-drag paints the map: the left button with the brush's symbol, the right button
+        // back to 0. The stroke is filled in between frames so fast drags don't leave gaps.
+        let paint_left = input.mouse_is_down(MouseButton::Left);
+        if input.modifiers.ctrl && (paint_left || input.mouse_is_down(MouseButton::Right)) {
+            let (x, y) = input.mouse_pos;
+            let cursor = screen.cell_at(x, y);
+            let value = if paint_left { brush.value } else { 0 };
+            brush.stroke(&mut machine, brush_last.unwrap_or(cursor), cursor, value);
+            brush_last = Some(cursor);
+            if !playing {
+                fb.update_buffer(screen.frame(&machine));
+            }
+        } else {
+            brush_last = None;
+        }
+
+        // Left click starts a new random machine with its head under the cursor. Shift-click
+        // restarts the current table on a blank map from there instead.
         if input.mouse_is_down(MouseButton::Left) {
-            playing = true;
-            machine.reset();
+            if !click_pressed && input.modifiers.ctrl {
+                // Painting; holding on after letting go of Ctrl doesn't spawn either.
+                click_pressed = true;
+            }
+            // I-click inspects the machine under the cursor instead.
+            if !click_pressed && input.key_is_down(VirtualKeyCode::I) {
+                let (x, y) = input.mouse_pos;
+                let (x, y) = screen.cell_at(x, y);
+                let x = x.rem_euclid(width as f64) as usize % width;
+                let y = y.rem_euclid(height as f64) as usize % height;
+                if inspect::picks(&machine, x, y) {
+                    print!("{}", inspect::report(&machine));
+                    match inspect::save_thumbnail(&machine, &options.screenshot_dir) {
+                        Ok(path) => println!("  table image {}", path.display()),
+                        Err(error) => eprintln!("could not save the table image: {}", error),
+                    }
+                    screen.inspected = true;
+                    fb.update_buffer(screen.frame(&machine));
+                } else {
+                    println!("no machine within {} cells of x {} y {}", inspect::PICK_RADIUS, x, y);
+                }
+                click_pressed = true;
+            }
+            if !click_pressed {
+                if input.modifiers.shift {
+                    machine.reset();
+                } else {
+                    end_trace(&mut trace, &machine, "replaced");
+                    machine = TuringMachine::new(12, 7, generation, mode, width, height, &mut rng);
+                    screen.inspected = false;
+                }
+                let (x, y) = input.mouse_pos;
+                let (x, y) = screen.cell_at(x, y);
+                machine.xpos = x.rem_euclid(width as f64) as usize % width;
+                // A line machine's live row is always drawn at the top, so only the column
+                // follows the cursor.
+                if mode != Mode::Line {
+                    machine.ypos = y.rem_euclid(height as f64) as usize % height;
+                }
+                println!("spawned at x {} y {}", machine.xpos, machine.ypos);
+                playing = true;
+                rewind.clear();
+                click_pressed = true;
+            }
+        } else {
+            click_pressed = false;
         }
 
-        if input.mouse_is_down(MouseButton::Right) {
+        if input.mouse_is_down(MouseButton::Right) && !input.modifiers.ctrl {
             playing = true;
-            machine = TuringMachine::new(12, 7);
+            end_trace(&mut trace, &machine, "replaced");
+            machine = TuringMachine::new(12, 7, generation, mode, width, height, &mut rng);
+            screen.inspected = false;
+            rewind.clear();
             previous = SystemTime::now();
         }
 
+        // B pauses and walks back through the rewind buffer, F walks forward again. Unpausing
+        // continues from whichever snapshot is on screen.
+        if input.key_is_down(VirtualKeyCode::B) {
+            if !b_pressed {
+                playing = false;
+                if rewind.step_back(&mut machine, &mut rng, effects.gravity.as_mut()) {
+                    fb.update_buffer(screen.frame(&machine));
+                }
+                b_pressed = true;
+            }
+        } else {
+            b_pressed = false;
+        }
+
+        if input.key_is_down(VirtualKeyCode::F) {
+            if !f_pressed {
+                if rewind.step_forward(&mut machine, &mut rng, effects.gravity.as_mut()) {
+                    fb.update_buffer(screen.frame(&machine));
+                }
+                f_pressed = true;
+            }
+        } else {
+            f_pressed = false;
+        }
+
+        // + and - double and halve the steps per frame.
+        let faster = input.key_is_down(VirtualKeyCode::Equals) || input.key_is_down(VirtualKeyCode::Add);
+        if faster || input.key_is_down(VirtualKeyCode::Minus) || input.key_is_down(VirtualKeyCode::Subtract) {
+            if !speed_pressed {
+                steps_per_frame = if faster {
+                    steps_per_frame.max(1).saturating_mul(2).min(MAX_KEY_STEPS_PER_FRAME.max(steps_per_frame))
+                } else {
+                    (steps_per_frame / 2).max(1)
+                };
+                println!("steps per frame {}", steps_per_frame);
+                speed_pressed = true;
+            }
+        } else {
+            speed_pressed = false;
+        }
+
+        // N runs a single step while paused and prints where the head ended up.
+        if input.key_is_down(VirtualKeyCode::N) {
+            if !n_pressed && !playing {
+                if rewind.is_rewinding() {
+                    rewind.resume();
+                }
+                advance_frame(
+                    &mut machine,
+                    1,
+                    &mut trace,
+                    &mut paths,
+                    &mut effects,
+                );
+                fb.update_buffer(screen.frame(&machine));
+                println!(
+                    "iteration {} state {} x {} y {} symbol {}",
+                    machine.itr_count,
+                    machine.state,
+                    machine.xpos,
+                    machine.ypos,
+                    machine.map[machine.width * machine.ypos + machine.xpos]
+                );
+            }
+            n_pressed = true;
+        } else {
+            n_pressed = false;
+        }
+
         if input.key_is_down(VirtualKeyCode::Space) {
             if !space_pressed {
                 playing = !playing;
+                if show_hud {
+                    update_hud(&mut screen, show_hud, &machine, shading.palette, fps, steps_per_frame, playing);
+                    fb.update_buffer(screen.frame(&machine));
+                }
                 space_pressed = true;
             }
         } else {
             space_pressed = false;
         }
 
+        if playing && rewind.is_rewinding() {
+            rewind.resume();
+        }
+
         if (seconds > 0.00) && playing {
             previous = SystemTime::now();
-            machine.update(50_000);
-            fb.update_buffer(&machine.map[..]);
-            println!("frequency {}", 1.0/seconds);
+            advance_frame(
+                &mut machine,
+                steps_per_frame,
+                &mut trace,
+                &mut paths,
+                &mut effects,
+            );
+            rewind.record(&machine, &rng, effects.gravity.as_ref());
+            fps = 1.0 / seconds;
+            update_hud(&mut screen, show_hud, &machine, shading.palette, fps, steps_per_frame, playing);
+            let frame = screen.frame(&machine);
+            fb.update_buffer(frame);
+            if let Some(recording) = &mut recorder {
+                if !recording.record(shading, frame) {
+                    eprintln!("recording fell behind, dropped a frame");
+                }
+            }
+            if let Some(speed) = &mut speed {
+                steps_per_frame = speed.update(steps_per_frame, seconds);
+            }
+            if options.frame_log {
+                println!(
+                    "frequency {} steps {} self-modifications {}",
+                    fps,
+                    steps_per_frame,
+                    machine.self_modifications
+                );
+                if profile_started.elapsed().unwrap().as_secs() >= 1 {
+                    report_action_profile(&mut machine);
+                    profile_started = SystemTime::now();
+                }
+            }
+
+            // The symbol histogram is a pass over the whole map, so it is only counted on the
+            // frames that report it.
+            stats_frames += 1;
+            if stats_frames >= options.stats_every {
+                stats_frames = 0;
+                let stats = Stats::of(&machine);
+                if options.verbose_stats {
+                    print!("{}", stats.pretty());
+                } else {
+                    println!("{}", stats.line());
+                }
+                if let Some(file) = &mut stats_file {
+                    if let Err(error) = writeln!(file, "{}", stats.csv_row()) {
+                        eprintln!("could not write stats: {}", error);
+                        stats_file = None;
+                    }
+                }
+            }
         }
 
         true
     });
+
+    end_trace(&mut trace, &machine, "exit");
+    if let Some(recording) = recorder {
+        report_recording(recording);
+    }
+    if let (Some(paths), Some(path)) = (&mut paths, &options.paths_svg) {
+        match paths.write_svg(path) {
+            Ok(()) => println!("wrote {}", path.display()),
+            Err(error) => eprintln!("could not write {}: {}", path.display(), error),
+        }
+    }
 }
 
-const COLOR_SYMBOLS: &str = r#"
-    void main_image( out vec4 r_frag_color, in vec2 uv )
-    {
-        int symbol = int(texture(u_buffer, uv).r * 255);
-        switch (symbol) {
-            case 0:
-                // Red
-                r_frag_color = vec4(255.0, 0.0, 0.0, 1.0);
-                break;
-            case 1:
-                // Black
-                r_frag_color = vec4(0.0, 0.0, 0.0, 1.0);
-                break;
-            case 2:
-                // White
-                r_frag_color = vec4(255.0, 255.0, 255.0, 1.0);
-                break;
-            case 3:
-                // Green
-                r_frag_color = vec4(0.0, 255.0, 0.0, 1.0);
-                break;
-            case 4:
-                // Blue
-                r_frag_color = vec4(0.0, 0.0, 255.0, 1.0);
-                break;
-            case 5:
-                // Yellow
-                r_frag_color = vec4(255.0, 255.0, 0.0, 1.0);
-                break;
-            case 6:
-                // Magenta
-                r_frag_color = vec4(255.0, 0.0, 255.0, 1.0);
-                break;
-        }
-    }
-"#;
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use art::shader::Symmetry;
+
+    fn effects() -> Effects {
+        Effects {
+            gravity: Some(Gravity::new(0.2, 4, 7)),
+            splat: None,
+            milestones: vec![5_000],
+            decay: Decay::Linear { amount: 1, every: 3 },
+        }
+    }
+
+    #[test]
+    fn warmup_matches_live_frames() {
+        let genome = "3,6,2,2,3,2,4,0,0,1,0,2,1,2,1,1,0,1,2,3,2,3,0,2,1,0,2,5,3,2,5,2,2,4,1,1,5,0,2,4,3,0,4,0,0,1,1,2,1,3,2,1,0,2,2,0";
+        let mut warmed = TuringMachine::from_string(genome, 64, 64).unwrap();
+        skip_ahead(&mut warmed, 20_000, 1_000, &mut effects(), false);
+
+        let mut live = TuringMachine::from_string(genome, 64, 64).unwrap();
+        let mut live_effects = effects();
+        for _ in 0..20 {
+            advance_frame(&mut live, 1_000, &mut None, &mut None, &mut live_effects);
+        }
+        assert_eq!(warmed.itr_count, live.itr_count);
+        assert!(warmed.map == live.map);
+    }
+
+    #[test]
+    fn zero_steps_per_frame_leaves_the_world_alone() {
+        let genome = "3,6,2,2,3,2,4,0,0,1,0,2,1,2,1,1,0,1,2,3,2,3,0,2,1,0,2,5,3,2,5,2,2,4,1,1,5,0,2,4,3,0,4,0,0,1,1,2,1,3,2,1,0,2,2,0";
+        let mut paused = TuringMachine::from_string(genome, 64, 64).unwrap();
+        let mut paused_effects = effects();
+        skip_ahead(&mut paused, 3_000, 1_000, &mut paused_effects, false);
+        let mut running = TuringMachine::from_string(genome, 64, 64).unwrap();
+        let mut running_effects = effects();
+        skip_ahead(&mut running, 3_000, 1_000, &mut running_effects, false);
+
+        let map = paused.map.clone();
+        let mut speed = SpeedController::new(60.0, 100, 10_000);
+        let mut steps = 0;
+        for _ in 0..1_000 {
+            advance_frame(&mut paused, steps, &mut None, &mut None, &mut paused_effects);
+            steps = speed.update(steps, 0.001);
+        }
+        assert_eq!(steps, 0);
+        assert!(paused.map == map);
+        assert_eq!((paused.xpos, paused.ypos, paused.itr_count), (running.xpos, running.ypos, 3_000));
+
+        // Turning stepping back on carries on as if the pause never happened, across the
+        // milestone and the decay ticks.
+        for _ in 0..5 {
+            advance_frame(&mut paused, 1_000, &mut None, &mut None, &mut paused_effects);
+            advance_frame(&mut running, 1_000, &mut None, &mut None, &mut running_effects);
+        }
+        assert!(paused.map == running.map);
+        assert_eq!(paused.state, running.state);
+    }
+
+    #[test]
+    fn previews_are_reproducible() {
+        // FNV-1a rather than the std hasher, whose output may change between Rust releases.
+        let fnv = |bytes: &[u8]| {
+            bytes
+                .iter()
+                .fold(0xcbf2_9ce4_8422_2325_u64, |hash, &byte| (hash ^ byte as u64).wrapping_mul(0x100_0000_01b3))
+        };
+        let genome = "3,6,2,2,3,2,4,0,0,1,0,2,1,2,1,1,0,1,2,3,2,3,0,2,1,0,2,5,3,2,5,2,2,4,1,1,5,0,2,4,3,0,4,0,0,1,1,2,1,3,2,1,0,2,2,0";
+        let image = preview(TuringMachine::from_string(genome, 64, 64).unwrap(), 20_000);
+        assert_eq!(image.dimensions(), (64, 64));
+        assert!(image == preview(TuringMachine::from_string(genome, 64, 64).unwrap(), 20_000));
+        assert_eq!(fnv(&image), 0x974b_84a6_da94_114c);
+    }
+
+    #[test]
+    fn screenshot_names_dont_collide() {
+        let dir = env::temp_dir().join(format!("art-screenshot-test-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let machine = TuringMachine::from_string("1,2,0,1,0,0,0,0", 8, 8).unwrap();
+
+        // The parts of a name after `art_<seed>_<iter>_`: the timestamp and anything after it.
+        let rest = |path: &Path| {
+            let name = path.file_name().unwrap().to_str().unwrap().to_string();
+            let rest = name.strip_prefix("art_264_0_").unwrap_or_else(|| panic!("{}", name)).to_string();
+            let parts: Vec<String> = rest.trim_end_matches(".png").split('_').map(String::from).collect();
+            assert!(u64::from_str(&parts[0]).is_ok(), "{}", name);
+            parts
+        };
+
+        let first = screenshot_path(&dir, &machine, 264, "");
+        assert_eq!(rest(&first).len(), 1);
+        fs::write(&first, b"").unwrap();
+        let second = screenshot_path(&dir, &machine, 264, "");
+        assert_ne!(first, second);
+        assert!(!second.exists());
+        // Within the same second the counter tells them apart.
+        if rest(&second)[0] == rest(&first)[0] {
+            assert_eq!(rest(&second)[1..], ["1"]);
+        }
+        assert_eq!(rest(&screenshot_path(&dir, &machine, 264, "raw"))[1..], ["raw"]);
+        fs::remove_dir_all(&dir).unwrap();
+    }
 
+    #[test]
+    fn screenshots_note_the_palette_and_symmetry() {
+        let dir = env::temp_dir().join(format!("art-metadata-test-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let machine = TuringMachine::from_string("1,2,0,1,0,0,0,0", 8, 8).unwrap();
+        let mut shading = Shading::new(machine.mode, 8, 8, machine.num_symbols);
+        shading.palette = Palette::Magma;
+        shading.symmetry = Symmetry::Four;
+
+        for &raw in &[false, true] {
+            let path = save_screenshot(&machine, &dir, 254, &shading, raw).unwrap();
+            let text = metadata::read(&path).unwrap();
+            assert!(text.contains(&("Palette".to_string(), "magma".to_string())), "{:?}", text);
+            assert!(text.contains(&("Symmetry".to_string(), "4-fold".to_string())), "{:?}", text);
+        }
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
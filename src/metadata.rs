@@ -0,0 +1,45 @@
+use std::fs::File;
+use std::io::{self, BufWriter};
+use std::path::Path;
+
+use image::{GrayImage, RgbImage};
+
+/// Saves `image` as a PNG at `path` with a text chunk per key and value in `text`, which image
+/// viewers list as the picture's properties.
+pub fn save_rgb(image: &RgbImage, path: &Path, text: &[(&str, String)]) -> io::Result<()> {
+    write(path, image.width(), image.height(), png::ColorType::Rgb, image.as_raw(), text)
+}
+
+/// `save_rgb` for a grayscale image.
+pub fn save_gray(image: &GrayImage, path: &Path, text: &[(&str, String)]) -> io::Result<()> {
+    write(path, image.width(), image.height(), png::ColorType::Grayscale, image.as_raw(), text)
+}
+
+/// Reads the text chunks back from the PNG at `path`.
+pub fn read(path: &Path) -> io::Result<Vec<(String, String)>> {
+    let reader = png::Decoder::new(File::open(path)?).read_info()?;
+    let info = reader.info();
+    Ok(info
+        .uncompressed_latin1_text
+        .iter()
+        .map(|chunk| (chunk.keyword.clone(), chunk.text.clone()))
+        .collect())
+}
+
+fn write(
+    path: &Path,
+    width: u32,
+    height: u32,
+    color: png::ColorType,
+    pixels: &[u8],
+    text: &[(&str, String)],
+) -> io::Result<()> {
+    let mut encoder = png::Encoder::new(BufWriter::new(File::create(path)?), width, height);
+    encoder.set_color(color);
+    encoder.set_depth(png::BitDepth::Eight);
+    for (key, value) in text {
+        encoder.add_text_chunk(key.to_string(), value.clone())?;
+    }
+    encoder.write_header()?.write_image_data(pixels)?;
+    Ok(())
+}
@@ -0,0 +1,180 @@
+use image::{GrayImage, Luma, Rgb, RgbImage};
+
+/// Colors given to the symbols. Each preset lists one color per symbol; symbols past the end of
+/// the list get its last color. Ramps instead spread their colors evenly over however many
+/// symbols the machine has.
+#[derive(Clone, Copy, PartialEq)]
+pub enum Palette {
+    /// The original red, black, white, green, blue, yellow, magenta set.
+    Classic,
+    Viridis,
+    Magma,
+    /// A light-to-dark blue ramp that stays inside what a CMYK press can print.
+    Cmyk,
+    /// Black background with everything else white.
+    Contrast,
+    /// Hues around the color wheel.
+    Rainbow,
+    /// Black to white.
+    Grayscale,
+    /// Deep blue to orange.
+    Gradient,
+}
+
+const GRADIENT_FROM: [f32; 3] = [16.0, 24.0, 72.0];
+const GRADIENT_TO: [f32; 3] = [255.0, 176.0, 48.0];
+
+/// Fully saturated, fully bright color at `hue`, which runs from 0 to 1 around the wheel.
+fn hue(hue: f32) -> [u8; 3] {
+    let h = hue * 6.0;
+    let x = 1.0 - (h % 2.0 - 1.0).abs();
+    let (r, g, b) = match h as u32 {
+        0 => (1.0, x, 0.0),
+        1 => (x, 1.0, 0.0),
+        2 => (0.0, 1.0, x),
+        3 => (0.0, x, 1.0),
+        4 => (x, 0.0, 1.0),
+        _ => (1.0, 0.0, x),
+    };
+    [(r * 255.0) as u8, (g * 255.0) as u8, (b * 255.0) as u8]
+}
+
+const PALETTES: &[(&str, Palette)] = &[
+    ("classic", Palette::Classic),
+    ("viridis", Palette::Viridis),
+    ("magma", Palette::Magma),
+    ("cmyk", Palette::Cmyk),
+    ("contrast", Palette::Contrast),
+    ("rainbow", Palette::Rainbow),
+    ("grayscale", Palette::Grayscale),
+    ("gradient", Palette::Gradient),
+];
+
+impl Palette {
+    pub fn from_name(name: &str) -> Option<Palette> {
+        PALETTES.iter().find(|&&(n, _)| n == name).map(|&(_, p)| p)
+    }
+
+    /// The `index`th palette in the order `next` cycles through.
+    pub fn nth(index: usize) -> Option<Palette> {
+        PALETTES.get(index).map(|&(_, p)| p)
+    }
+
+    pub fn next(self) -> Palette {
+        match self {
+            Palette::Classic => Palette::Viridis,
+            Palette::Viridis => Palette::Magma,
+            Palette::Magma => Palette::Cmyk,
+            Palette::Cmyk => Palette::Contrast,
+            Palette::Contrast => Palette::Rainbow,
+            Palette::Rainbow => Palette::Grayscale,
+            Palette::Grayscale => Palette::Gradient,
+            Palette::Gradient => Palette::Classic,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        PALETTES.iter().find(|&&(_, p)| p == self).unwrap().0
+    }
+
+    /// The preset's color list, or `None` for ramps.
+    fn preset(self) -> Option<&'static [[u8; 3]]> {
+        Some(match self {
+            Palette::Classic => &[
+                [255, 0, 0],
+                [0, 0, 0],
+                [255, 255, 255],
+                [0, 255, 0],
+                [0, 0, 255],
+                [255, 255, 0],
+                [255, 0, 255],
+            ],
+            Palette::Viridis => &[
+                [0x44, 0x01, 0x54],
+                [0x46, 0x32, 0x7e],
+                [0x36, 0x5c, 0x8d],
+                [0x27, 0x7f, 0x8e],
+                [0x1f, 0xa1, 0x87],
+                [0x4a, 0xc1, 0x6d],
+                [0xa0, 0xda, 0x39],
+                [0xfd, 0xe7, 0x25],
+            ],
+            Palette::Magma => &[
+                [0x00, 0x00, 0x04],
+                [0x1c, 0x10, 0x44],
+                [0x4f, 0x12, 0x7b],
+                [0x81, 0x25, 0x81],
+                [0xb5, 0x36, 0x7a],
+                [0xe5, 0x59, 0x64],
+                [0xfb, 0x87, 0x61],
+                [0xfc, 0xfd, 0xbf],
+            ],
+            Palette::Cmyk => &[
+                [0xff, 0xff, 0xff],
+                [0xd9, 0xec, 0xf2],
+                [0xa6, 0xce, 0xe3],
+                [0x5f, 0xa8, 0xc8],
+                [0x1f, 0x78, 0xb4],
+                [0x2b, 0x4f, 0x8c],
+                [0x25, 0x30, 0x60],
+                [0x00, 0x00, 0x00],
+            ],
+            Palette::Contrast => &[[0, 0, 0], [255, 255, 255]],
+            Palette::Rainbow | Palette::Grayscale | Palette::Gradient => return None,
+        })
+    }
+
+    /// One color per symbol of a machine with `num_symbols` symbols. Symbols past the end get
+    /// the last color.
+    pub fn colors(self, num_symbols: u16) -> Vec<[u8; 3]> {
+        if let Some(colors) = self.preset() {
+            return colors.to_vec();
+        }
+        let count = num_symbols.max(2) as f32;
+        (0..num_symbols.max(2))
+            .map(|symbol| {
+                let t = symbol as f32 / (count - 1.0);
+                match self {
+                    // Stop short of a full turn so the last symbol doesn't wrap back to red.
+                    Palette::Rainbow => hue(symbol as f32 / count),
+                    Palette::Grayscale => [(t * 255.0) as u8; 3],
+                    _ => {
+                        let mix = |i: usize| (GRADIENT_FROM[i] + (GRADIENT_TO[i] - GRADIENT_FROM[i]) * t) as u8;
+                        [mix(0), mix(1), mix(2)]
+                    }
+                }
+            })
+            .collect()
+    }
+
+    /// The symbol of a machine with `num_symbols` symbols that gets the darkest color, going by
+    /// luma.
+    pub fn darkest(self, num_symbols: u16) -> u8 {
+        let colors = self.colors(num_symbols);
+        let luma = |[r, g, b]: [u8; 3]| 299 * r as u32 + 587 * g as u32 + 114 * b as u32;
+        (0..num_symbols as usize)
+            .min_by_key(|&symbol| luma(colors[symbol.min(colors.len() - 1)]))
+            .unwrap() as u8
+    }
+
+    /// Colors a `width` x `height` buffer of symbols on the CPU, as the shader draws them for a
+    /// machine with `num_symbols` symbols. Row 0 of the buffer is at the bottom of the window, so
+    /// rows are flipped to get the same picture.
+    pub fn image(self, buffer: &[u8], width: usize, height: usize, num_symbols: u16) -> RgbImage {
+        let colors = self.colors(num_symbols);
+        RgbImage::from_fn(width as u32, height as u32, |x, y| {
+            let row = height - 1 - y as usize;
+            let symbol = buffer[width * row + x as usize] as usize;
+            Rgb(colors[symbol.min(colors.len() - 1)])
+        })
+    }
+}
+
+/// The raw symbols of a `width` x `height` buffer as a grayscale image, flipped like
+/// `Palette::image`, so it can be colored again later.
+pub fn symbols_image(buffer: &[u8], width: usize, height: usize) -> GrayImage {
+    GrayImage::from_fn(width as u32, height as u32, |x, y| {
+        let row = height - 1 - y as usize;
+        Luma([buffer[width * row + x as usize]])
+    })
+}
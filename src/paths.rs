@@ -0,0 +1,151 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+/// Points closer than this many cells to the line through the previous two points are merged
+/// into that line.
+const TOLERANCE: f64 = 0.5;
+
+/// Records where a machine's head goes as polylines for plotter-style SVG output. A polyline is
+/// broken whenever the head jumps by more than one cell, which happens when it wraps around the
+/// edge of the map. To keep long runs bounded only every `stride`-th position is kept, and the
+/// stride doubles (thinning out what was already recorded) whenever `max_points` is exceeded.
+pub struct PathRecorder {
+    lines: Vec<Vec<(usize, usize)>>,
+    current: Vec<(usize, usize)>,
+    last: Option<(usize, usize)>,
+    points: usize,
+    max_points: usize,
+    stride: u32,
+    steps: u32,
+    width: usize,
+    height: usize,
+}
+
+fn is_jump(from: (usize, usize), to: (usize, usize)) -> bool {
+    let dx = (from.0 as isize - to.0 as isize).abs();
+    let dy = (from.1 as isize - to.1 as isize).abs();
+    dx > 1 || dy > 1
+}
+
+/// Distance of `point` from the infinite line through `a` and `b`.
+fn distance_to_line(a: (usize, usize), b: (usize, usize), point: (usize, usize)) -> f64 {
+    let (ax, ay) = (a.0 as f64, a.1 as f64);
+    let (bx, by) = (b.0 as f64, b.1 as f64);
+    let (px, py) = (point.0 as f64, point.1 as f64);
+
+    let length = ((bx - ax).powi(2) + (by - ay).powi(2)).sqrt();
+    if length == 0.0 {
+        return ((px - ax).powi(2) + (py - ay).powi(2)).sqrt();
+    }
+    ((bx - ax) * (ay - py) - (ax - px) * (by - ay)).abs() / length
+}
+
+/// Keeps every other point of `line`, always keeping both ends.
+fn thin(line: &mut Vec<(usize, usize)>) {
+    if line.len() <= 2 {
+        return;
+    }
+    let last = line[line.len() - 1];
+    let mut i = 0;
+    line.retain(|_| {
+        i += 1;
+        i % 2 == 1
+    });
+    if line[line.len() - 1] != last {
+        line.push(last);
+    }
+}
+
+impl PathRecorder {
+    /// Records paths on a map of `width` x `height` cells.
+    pub fn new(max_points: usize, width: usize, height: usize) -> PathRecorder {
+        assert!(max_points >= 2, "path recording needs room for at least 2 points");
+
+        PathRecorder {
+            lines: Vec::new(),
+            current: Vec::new(),
+            last: None,
+            points: 0,
+            max_points,
+            stride: 1,
+            steps: 0,
+            width,
+            height,
+        }
+    }
+
+    /// Called after every step with the new head position.
+    pub fn record(&mut self, x: usize, y: usize) {
+        let position = (x, y);
+        if let Some(last) = self.last {
+            if is_jump(last, position) {
+                self.break_line();
+                self.steps = 0;
+            }
+        }
+        self.last = Some(position);
+
+        self.steps += 1;
+        if self.steps < self.stride && !self.current.is_empty() {
+            return;
+        }
+        self.steps = 0;
+
+        let n = self.current.len();
+        if n >= 2 && distance_to_line(self.current[n - 2], self.current[n - 1], position) < TOLERANCE {
+            self.current[n - 1] = position;
+        } else {
+            self.current.push(position);
+            self.points += 1;
+        }
+
+        if self.points > self.max_points {
+            self.stride *= 2;
+            for line in &mut self.lines {
+                thin(line);
+            }
+            thin(&mut self.current);
+            self.points = self.lines.iter().map(|l| l.len()).sum::<usize>() + self.current.len();
+        }
+    }
+
+    /// Ends the current polyline, used on wraps and when the machine is replaced or reset.
+    pub fn break_line(&mut self) {
+        let line = std::mem::take(&mut self.current);
+        if line.len() >= 2 {
+            self.lines.push(line);
+        } else {
+            self.points -= line.len();
+        }
+        self.last = None;
+    }
+
+    /// Writes every recorded polyline as an SVG whose viewBox is exactly the map. Rows are
+    /// flipped so the picture has the same orientation as the window.
+    pub fn write_svg(&mut self, path: &Path) -> io::Result<()> {
+        self.break_line();
+
+        let mut writer = BufWriter::new(File::create(path)?);
+        writeln!(
+            writer,
+            r#"<svg xmlns="http://www.w3.org/2000/svg" viewBox="0 0 {} {}" width="{}" height="{}">"#,
+            self.width, self.height, self.width, self.height
+        )?;
+        writeln!(
+            writer,
+            r#"<g fill="none" stroke="black" stroke-width="0.5" stroke-linejoin="round" stroke-linecap="round">"#
+        )?;
+        for line in &self.lines {
+            write!(writer, r#"<polyline points=""#)?;
+            for (i, &(x, y)) in line.iter().enumerate() {
+                let separator = if i == 0 { "" } else { " " };
+                write!(writer, "{}{}.5,{}.5", separator, x, self.height - 1 - y)?;
+            }
+            writeln!(writer, r#""/>"#)?;
+        }
+        writeln!(writer, "</g>")?;
+        writeln!(writer, "</svg>")?;
+        writer.flush()
+    }
+}
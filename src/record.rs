@@ -0,0 +1,111 @@
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::sync::mpsc::{self, SyncSender, TrySendError};
+use std::thread::{self, JoinHandle};
+
+use crate::shader::Shading;
+
+/// Frames that can wait for the writer before new ones are dropped.
+const QUEUE: usize = 32;
+
+/// Writes frames as numbered PNGs on a background thread so encoding never holds up the
+/// simulation. If the writer falls behind, frames are dropped rather than queued without bound.
+pub struct Recorder {
+    sender: SyncSender<(Shading, Vec<u8>)>,
+    writer: JoinHandle<Written>,
+    dir: PathBuf,
+    dropped: u32,
+}
+
+impl Recorder {
+    /// Starts writing `width` x `height` frames with `channels` bytes per pixel into `dir`,
+    /// which is created if needed.
+    pub fn start(dir: PathBuf, width: usize, height: usize, channels: usize) -> io::Result<Recorder> {
+        fs::create_dir_all(&dir)?;
+        let (sender, frames) = mpsc::sync_channel::<(Shading, Vec<u8>)>(QUEUE);
+        let out = dir.clone();
+        let writer = thread::spawn(move || {
+            let mut written = Written::default();
+            for (shading, frame) in frames {
+                let path = out.join(format!("frame-{:06}.png", written.frames));
+                match shading.image(&frame, channels, width, height).save(&path) {
+                    Ok(()) => written.frames += 1,
+                    Err(error) => {
+                        written.failed += 1;
+                        written.last_error = Some(format!("could not write {}: {}", path.display(), error));
+                    }
+                }
+            }
+            written
+        });
+
+        Ok(Recorder {
+            sender,
+            writer,
+            dir,
+            dropped: 0,
+        })
+    }
+
+    /// Queues a window buffer to be drawn with `shading`, as it is on screen, or drops it if the
+    /// queue is full. Returns whether the frame was queued.
+    pub fn record(&mut self, shading: Shading, frame: &[u8]) -> bool {
+        if let Err(TrySendError::Full(_)) = self.sender.try_send((shading, frame.to_vec())) {
+            self.dropped += 1;
+            return false;
+        }
+        true
+    }
+
+    /// Waits for the queued frames to be written and reports how many made it to disk.
+    pub fn finish(self) -> Summary {
+        drop(self.sender);
+        let written = self.writer.join().expect("recording thread panicked");
+        Summary {
+            dir: self.dir,
+            written,
+            dropped: self.dropped,
+        }
+    }
+}
+
+/// What the writer thread got through.
+#[derive(Default)]
+struct Written {
+    frames: u32,
+    /// Frames that couldn't be saved.
+    failed: u32,
+    last_error: Option<String>,
+}
+
+/// How a recording went, for whoever stopped it to report.
+pub struct Summary {
+    dir: PathBuf,
+    written: Written,
+    dropped: u32,
+}
+
+impl Summary {
+    /// The last error writing a frame, if any frame failed.
+    pub fn last_error(&self) -> Option<&str> {
+        self.written.last_error.as_deref()
+    }
+}
+
+impl fmt::Display for Summary {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "wrote {} frames to {} ({} dropped",
+            self.written.frames,
+            self.dir.display(),
+            self.dropped
+        )?;
+        if self.written.failed > 0 {
+            write!(f, ", {} failed", self.written.failed)?;
+        }
+        write!(f, ")")
+    }
+}
@@ -0,0 +1,44 @@
+use std::io::{self, BufRead};
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+
+use art::command::{parse, Command, PARAMETERS};
+
+fn usage() -> String {
+    let names: Vec<&str> = PARAMETERS.iter().map(|&(name, _)| name).collect();
+    format!(
+        "commands: pause | play | step <n> | reset | set <{}> <value> | spawn <machine string> | save <path>",
+        names.join("|")
+    )
+}
+
+/// Reads newline separated commands from stdin on a background thread. Commands are handed to
+/// the main loop through the returned channel and applied between frames; lines that don't
+/// parse are reported on stderr together with the usage text.
+pub fn spawn_reader() -> Receiver<Command> {
+    let (sender, receiver) = mpsc::channel();
+
+    thread::spawn(move || {
+        let stdin = io::stdin();
+        for line in stdin.lock().lines() {
+            let line = match line {
+                Ok(line) => line,
+                Err(_) => break,
+            };
+            if line.trim().is_empty() {
+                continue;
+            }
+
+            match parse(&line) {
+                Ok(command) => {
+                    if sender.send(command).is_err() {
+                        break;
+                    }
+                }
+                Err(error) => eprintln!("{}\n{}", error, usage()),
+            }
+        }
+    });
+
+    receiver
+}
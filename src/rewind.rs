@@ -0,0 +1,286 @@
+use std::collections::VecDeque;
+
+use rand::rngs::SmallRng;
+
+use crate::gravity::Gravity;
+use crate::{Mode, TableSymmetry, Transition, TuringMachine, ACTIONS};
+
+/// A run-length encoded copy of the map and any extra layers plus the head and table of the
+/// machine, its counters and the state of the run's random number generators, taken at one
+/// point of the run. The table is kept because self-modifying machines rewrite it as they go,
+/// and its shape and the map size with it, so a snapshot can also go back to a machine that
+/// has since been replaced by one of another shape.
+/// Runs are stored as (length, symbol) byte pairs so a noisy map costs at most twice its raw
+/// size while the mostly blank maps early in a run shrink to almost nothing.
+pub struct Snapshot {
+    runs: Vec<u8>,
+    width: usize,
+    height: usize,
+    num_layers: u8,
+    num_states: u16,
+    num_symbols: u16,
+    mode: Mode,
+    sensing: bool,
+    symmetry: TableSymmetry,
+    version: u8,
+    strides: (usize, usize),
+    state: u8,
+    layer: u8,
+    xpos: usize,
+    ypos: usize,
+    itr_count: u32,
+    table: Vec<Transition>,
+    self_modifications: u32,
+    action_counts: [u32; ACTIONS.len()],
+    /// Restored with the rest so a run resumed from here draws the same numbers again.
+    rng: SmallRng,
+    /// The gravity's RNG, if the run has gravity.
+    gravity: Option<SmallRng>,
+}
+
+impl Snapshot {
+    pub fn take(machine: &TuringMachine, rng: &SmallRng, gravity: Option<&Gravity>) -> Snapshot {
+        let mut runs = Vec::new();
+        let mut cells = machine.map.iter().chain(machine.layers.iter().flatten());
+        let mut current = *cells.next().unwrap();
+        let mut length = 1u8;
+        for &symbol in cells {
+            if symbol == current && length < u8::MAX {
+                length += 1;
+            } else {
+                runs.push(length);
+                runs.push(current);
+                current = symbol;
+                length = 1;
+            }
+        }
+        runs.push(length);
+        runs.push(current);
+
+        Snapshot {
+            runs,
+            width: machine.width,
+            height: machine.height,
+            num_layers: machine.num_layers(),
+            num_states: machine.num_states,
+            num_symbols: machine.num_symbols,
+            mode: machine.mode,
+            sensing: machine.sensing,
+            symmetry: machine.symmetry,
+            version: machine.version,
+            strides: machine.strides,
+            state: machine.state,
+            layer: machine.layer,
+            xpos: machine.xpos,
+            ypos: machine.ypos,
+            itr_count: machine.itr_count,
+            table: machine.table.clone(),
+            self_modifications: machine.self_modifications,
+            action_counts: machine.action_counts,
+            rng: rng.clone(),
+            gravity: gravity.map(|gravity| gravity.rng.clone()),
+        }
+    }
+
+    /// Puts the map, head, table, `rng` and the gravity's RNG back as they were. `machine` gets
+    /// the map size, layers, topology and table layout of the machine the snapshot was taken
+    /// from, whatever it had before.
+    pub fn restore(&self, machine: &mut TuringMachine, rng: &mut SmallRng, gravity: Option<&mut Gravity>) {
+        let size = self.width * self.height;
+        machine.width = self.width;
+        machine.height = self.height;
+        machine.map.resize(size, 0);
+        machine.layers.resize(self.num_layers as usize - 1, Vec::new());
+        for plane in &mut machine.layers {
+            plane.resize(size, 0);
+        }
+        machine.num_states = self.num_states;
+        machine.num_symbols = self.num_symbols;
+        machine.mode = self.mode;
+        machine.sensing = self.sensing;
+        machine.symmetry = self.symmetry;
+        machine.version = self.version;
+        machine.strides = self.strides;
+
+        let mut cells = machine.map.iter_mut().chain(machine.layers.iter_mut().flatten());
+        for run in self.runs.chunks(2) {
+            for cell in cells.by_ref().take(run[0] as usize) {
+                *cell = run[1];
+            }
+        }
+        debug_assert!(cells.next().is_none());
+
+        machine.state = self.state;
+        machine.layer = self.layer;
+        machine.xpos = self.xpos;
+        machine.ypos = self.ypos;
+        machine.itr_count = self.itr_count;
+        machine.table.clone_from(&self.table);
+        machine.self_modifications = self.self_modifications;
+        machine.action_counts = self.action_counts;
+        *rng = self.rng.clone();
+        if let (Some(gravity), Some(state)) = (gravity, &self.gravity) {
+            gravity.rng = state.clone();
+        }
+    }
+}
+
+/// Ring buffer of recent snapshots that lets the run be stepped backwards. A snapshot is taken
+/// every `interval` frames and only the newest `capacity` are kept, so memory stays bounded no
+/// matter how long the run goes on.
+pub struct Rewind {
+    snapshots: VecDeque<Snapshot>,
+    capacity: usize,
+    interval: u32,
+    frames: u32,
+    /// Index of the snapshot currently on screen while rewinding.
+    cursor: Option<usize>,
+}
+
+impl Rewind {
+    pub fn new(capacity: usize, interval: u32) -> Rewind {
+        assert!(interval >= 1, "rewind interval must be at least 1 frame");
+
+        Rewind {
+            snapshots: VecDeque::with_capacity(capacity),
+            capacity,
+            interval,
+            frames: 0,
+            cursor: None,
+        }
+    }
+
+    /// Forgets every snapshot, used when the machine or its map is replaced wholesale.
+    pub fn clear(&mut self) {
+        self.snapshots.clear();
+        self.frames = 0;
+        self.cursor = None;
+    }
+
+    /// Called once per simulated frame, snapshots the machine and the RNGs when the interval
+    /// has elapsed.
+    pub fn record(&mut self, machine: &TuringMachine, rng: &SmallRng, gravity: Option<&Gravity>) {
+        if self.capacity == 0 {
+            return;
+        }
+
+        self.frames += 1;
+        if self.frames < self.interval {
+            return;
+        }
+        self.frames = 0;
+
+        if self.snapshots.len() == self.capacity {
+            self.snapshots.pop_front();
+        }
+        self.snapshots.push_back(Snapshot::take(machine, rng, gravity));
+    }
+
+    pub fn is_rewinding(&self) -> bool {
+        self.cursor.is_some()
+    }
+
+    /// Moves one snapshot further into the past and shows it. Returns false once the oldest
+    /// snapshot is already displayed.
+    pub fn step_back(
+        &mut self,
+        machine: &mut TuringMachine,
+        rng: &mut SmallRng,
+        gravity: Option<&mut Gravity>,
+    ) -> bool {
+        let index = match self.cursor {
+            Some(0) => return false,
+            Some(i) => i - 1,
+            None if self.snapshots.is_empty() => return false,
+            None => self.snapshots.len() - 1,
+        };
+
+        self.snapshots[index].restore(machine, rng, gravity);
+        self.cursor = Some(index);
+        true
+    }
+
+    /// Moves one snapshot back towards the present while still rewinding.
+    pub fn step_forward(
+        &mut self,
+        machine: &mut TuringMachine,
+        rng: &mut SmallRng,
+        gravity: Option<&mut Gravity>,
+    ) -> bool {
+        match self.cursor {
+            Some(i) if i + 1 < self.snapshots.len() => {
+                self.snapshots[i + 1].restore(machine, rng, gravity);
+                self.cursor = Some(i + 1);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Continues the run from the displayed snapshot, discarding every newer one.
+    pub fn resume(&mut self) {
+        if let Some(i) = self.cursor.take() {
+            self.snapshots.truncate(i + 1);
+            self.frames = 0;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rand::{Rng, SeedableRng};
+
+    #[test]
+    fn stepping_back_restores_the_map_and_rng() {
+        let mut machine = TuringMachine::from_string("1,2,0,1,3,0,0,1", 16, 16).unwrap();
+        let mut rng = SmallRng::seed_from_u64(212);
+        let mut rewind = Rewind::new(4, 1);
+        machine.step(40);
+        rewind.record(&machine, &rng, None);
+        let (map, head) = (machine.map.clone(), (machine.xpos, machine.ypos, machine.itr_count));
+        let draw: u64 = rng.clone().gen();
+
+        machine.step(100);
+        rng.gen::<u64>();
+        rewind.record(&machine, &rng, None);
+        assert!(rewind.step_back(&mut machine, &mut rng, None));
+        assert!(rewind.step_back(&mut machine, &mut rng, None));
+        assert!(!rewind.step_back(&mut machine, &mut rng, None));
+
+        assert!(machine.map == map);
+        assert_eq!((machine.xpos, machine.ypos, machine.itr_count), head);
+        assert_eq!(rng.gen::<u64>(), draw);
+    }
+
+    #[test]
+    fn restores_a_self_modified_table() {
+        // Symbol 0 is overwritten with 1 and rewrites an entry, symbol 1 with 0 and moves right.
+        let mut machine = TuringMachine::from_string("v3:1,2,0,1,8,0,0,1", 16, 16).unwrap();
+        let mut rng = SmallRng::seed_from_u64(223);
+        let snapshot = Snapshot::take(&machine, &rng, None);
+        let genome = machine.to_string();
+
+        machine.step(10);
+        assert!(machine.self_modifications > 0);
+        assert_ne!(machine.to_string(), genome);
+
+        snapshot.restore(&mut machine, &mut rng, None);
+        assert_eq!(machine.to_string(), genome);
+        assert_eq!(machine.self_modifications, 0);
+    }
+
+    #[test]
+    fn restores_the_gravity_rng() {
+        let mut machine = TuringMachine::from_string("1,2,0,1,3,0,0,1", 16, 16).unwrap();
+        let mut rng = SmallRng::seed_from_u64(237);
+        let mut gravity = Gravity::new(0.5, 1, 237);
+        let snapshot = Snapshot::take(&machine, &rng, Some(&gravity));
+        let draws: Vec<u64> = (0..4).map(|_| gravity.rng.gen()).collect();
+
+        snapshot.restore(&mut machine, &mut rng, Some(&mut gravity));
+        let again: Vec<u64> = (0..4).map(|_| gravity.rng.gen()).collect();
+        assert_eq!(again, draws);
+    }
+}
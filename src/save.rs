@@ -0,0 +1,180 @@
+use std::fs::File;
+use std::convert::TryFrom;
+use std::io::{self, BufRead, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+use std::str::FromStr;
+
+use crate::{Mode, TuringMachine};
+
+const MAGIC: &str = "art-save 2";
+/// Saves from before layers, which have no read layer on the head line and no extra planes.
+const MAGIC_V1: &str = "art-save 1";
+
+/// Save files hold everything needed to carry on with a run: a header line, the machine string
+/// (which keeps the table as it is now, self-modifications included), the map size, the head,
+/// counters and read layer, and finally the raw map followed by any extra layers, one byte per
+/// cell.
+pub fn write(machine: &TuringMachine, path: &Path) -> io::Result<()> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    writeln!(writer, "{}", MAGIC)?;
+    writeln!(writer, "{}", machine)?;
+    writeln!(writer, "{} {}", machine.width, machine.height)?;
+    writeln!(
+        writer,
+        "{} {} {} {} {} {}",
+        machine.state, machine.xpos, machine.ypos, machine.itr_count, machine.self_modifications, machine.layer
+    )?;
+    writer.write_all(&machine.map)?;
+    for plane in &machine.layers {
+        writer.write_all(plane)?;
+    }
+    writer.flush()
+}
+
+fn invalid(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+fn line(reader: &mut impl BufRead) -> io::Result<String> {
+    let mut line = String::new();
+    if reader.read_line(&mut line)? == 0 {
+        return Err(invalid("save file ends early".to_string()));
+    }
+    Ok(line.trim_end().to_string())
+}
+
+fn numbers(line: &str, count: usize) -> io::Result<Vec<usize>> {
+    let numbers = line
+        .split(' ')
+        .map(usize::from_str)
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|_| invalid(format!("not a list of numbers: {}", line)))?;
+    if numbers.len() != count {
+        return Err(invalid(format!("expected {} numbers: {}", count, line)));
+    }
+    Ok(numbers)
+}
+
+/// A counter from the head line, which has to fit the `u32` it is kept in.
+fn counter(value: usize, name: &str) -> io::Result<u32> {
+    u32::try_from(value).map_err(|_| invalid(format!("{} {} is too large", name, value)))
+}
+
+/// Reads a save file back into a machine. Saves made on a map of a different size than
+/// `width` x `height` are refused.
+pub fn read(path: &Path, width: usize, height: usize) -> io::Result<TuringMachine> {
+    let mut reader = BufReader::new(File::open(path)?);
+    let old = match line(&mut reader)?.as_str() {
+        MAGIC => false,
+        MAGIC_V1 => true,
+        _ => return Err(invalid("not an art save file".to_string())),
+    };
+    let genome = line(&mut reader)?;
+
+    let size = numbers(&line(&mut reader)?, 2)?;
+    if (size[0], size[1]) != (width, height) {
+        return Err(invalid(format!(
+            "save is for a {}x{} map but the map is {}x{}",
+            size[0], size[1], width, height
+        )));
+    }
+
+    let mut machine =
+        TuringMachine::from_string(&genome, width, height).map_err(|error| invalid(error.to_string()))?;
+    let mut head = numbers(&line(&mut reader)?, if old { 5 } else { 6 })?;
+    head.resize(6, 0);
+    // Hex machines leave the last row of an odd height map unused.
+    let rows = if machine.mode == Mode::Hex { machine.hex_height() } else { height };
+    if head[0] >= machine.num_states as usize
+        || head[1] >= width
+        || head[2] >= rows
+        || head[5] >= machine.num_layers() as usize
+    {
+        return Err(invalid("head is outside the machine or the map".to_string()));
+    }
+    machine.state = head[0] as u8;
+    machine.xpos = head[1];
+    machine.ypos = head[2];
+    machine.itr_count = counter(head[3], "iteration count")?;
+    machine.self_modifications = counter(head[4], "self-modification count")?;
+    machine.layer = head[5] as u8;
+
+    reader.read_exact(&mut machine.map)?;
+    for plane in &mut machine.layers {
+        reader.read_exact(plane)?;
+    }
+    Ok(machine)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rand::{rngs::SmallRng, SeedableRng};
+
+    use crate::{GenerationParams, Mode, StartState, TableSymmetry};
+
+    #[test]
+    fn loaded_runs_carry_on_like_the_original() {
+        let path = std::env::temp_dir().join(format!("art-save-test-{}.save", std::process::id()));
+        let params = GenerationParams {
+            sparse_bias: 0.0,
+            self_modify: true,
+            symmetry: TableSymmetry::None,
+            start: StartState::Random,
+            layers: 2,
+            sensing: false,
+        };
+        let mut rng = SmallRng::seed_from_u64(265);
+        let mut original = TuringMachine::new(8, 5, params, Mode::Plane, 40, 30, &mut rng);
+        original.step(50_000);
+
+        write(&original, &path).unwrap();
+        let refused = read(&path, 30, 40).err().unwrap();
+        let mut loaded = read(&path, 40, 30).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(refused.to_string(), "save is for a 40x30 map but the map is 30x40");
+
+        for _ in 0..10 {
+            original.step(10_000);
+            loaded.step(10_000);
+            assert_eq!(loaded.to_string(), original.to_string());
+            assert!(loaded.map == original.map && loaded.layers == original.layers);
+            assert_eq!((loaded.xpos, loaded.ypos, loaded.state), (original.xpos, original.ypos, original.state));
+            assert_eq!(loaded.itr_count, original.itr_count);
+        }
+    }
+
+    /// Reads a save of `genome` on a `width` x `height` blank map with `head` as its head line.
+    fn read_head(genome: &str, width: usize, height: usize, head: &str) -> io::Result<TuringMachine> {
+        let path = std::env::temp_dir().join(format!("art-head-test-{}-{}.save", std::process::id(), head));
+        let mut save = format!("{}\n{}\n{} {}\n{}\n", MAGIC, genome, width, height, head).into_bytes();
+        save.resize(save.len() + width * height, 0);
+        std::fs::write(&path, save).unwrap();
+        let machine = read(&path, width, height);
+        std::fs::remove_file(&path).unwrap();
+        machine
+    }
+
+    #[test]
+    fn refuses_heads_off_the_machine() {
+        let plane = "v3:1,2,0,0,0,0,1,0";
+        assert!(read_head(plane, 8, 7, "0 7 6 0 0 0").is_ok());
+        assert_eq!(
+            read_head(plane, 8, 7, "0 0 0 4294967296 0 0").err().unwrap().to_string(),
+            "iteration count 4294967296 is too large"
+        );
+        assert_eq!(
+            read_head(plane, 8, 7, "0 0 0 0 4294967296 0").err().unwrap().to_string(),
+            "self-modification count 4294967296 is too large"
+        );
+
+        // The last row of an odd height hex map is never used.
+        let hex = "v3:hex:1,2,0,0,0,0,1,0";
+        assert!(read_head(hex, 8, 7, "0 0 5 0 0 0").is_ok());
+        assert_eq!(
+            read_head(hex, 8, 7, "0 0 6 0 0 0").err().unwrap().to_string(),
+            "head is outside the machine or the map"
+        );
+    }
+}
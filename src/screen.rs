@@ -0,0 +1,261 @@
+use crate::hud;
+use crate::{Mode, TuringMachine};
+
+/// Highest zoom the view goes to.
+pub const MAX_ZOOM: usize = 16;
+
+/// Value drawn around the head when the marker is on. Symbols never get this high, so the
+/// shader can give it a color of its own.
+pub const HEAD_MARKER: u8 = 255;
+
+/// Value drawn in the ring around an inspected machine, with a shader color of its own like
+/// `HEAD_MARKER`. Symbols stay below it too.
+pub const INSPECT_MARKER: u8 = 254;
+
+/// Cells between the head and the ring drawn around an inspected machine, which is kept clear
+/// of the head marker's ring so the two can be told apart.
+const INSPECTED_REACH: usize = 3;
+
+/// Builds the buffer shown in the window. At `scale` 1 that is just the machine's display. At
+/// higher scales every cell becomes a `scale` x `scale` block so large prints get texture
+/// instead of flat squares: a few cells of each block, picked from the genome hash and the
+/// symbol, are drawn one symbol darker. Only the picture changes; the machine still runs on
+/// the logical map.
+///
+/// With two channels every pixel is followed by the cell of layer 1 under it, which the shader
+/// uses to shade the color. Machines without a layer 1 get zeros there.
+///
+/// The window shows a view into that picture: `zoom` times magnified, with nearest neighbour
+/// sampling, and panned so the cell at `view_x`, `view_y` sits in the bottom left corner.
+pub struct Screen {
+    /// Draws a ring of `HEAD_MARKER` around the head so it can be found on a busy map.
+    pub head_marker: bool,
+    /// Draws a wider ring of `INSPECT_MARKER` around the head of a machine picked for
+    /// inspection.
+    pub inspected: bool,
+    /// Lines of text drawn over the top left corner, none when empty. They are only drawn into
+    /// the window buffer, never into the map.
+    pub hud: Vec<String>,
+    /// Symbol the HUD text is set on, see `hud::draw`.
+    pub hud_paper: u8,
+    scale: usize,
+    width: usize,
+    height: usize,
+    channels: usize,
+    display: Vec<u8>,
+    upscaled: Vec<u8>,
+    marked: Vec<u8>,
+    packed: Vec<u8>,
+    zoom: usize,
+    view_x: usize,
+    view_y: usize,
+    view: Vec<u8>,
+}
+
+impl Screen {
+    /// A screen for a map of `width` x `height` cells, with one or two bytes per pixel.
+    pub fn new(scale: usize, width: usize, height: usize, channels: usize) -> Screen {
+        assert!(scale >= 1, "scale must be at least 1");
+        assert!(channels == 1 || channels == 2, "screens have one or two channels");
+
+        Screen {
+            head_marker: false,
+            inspected: false,
+            hud: Vec::new(),
+            hud_paper: 0,
+            scale,
+            width,
+            height,
+            channels,
+            display: Vec::with_capacity(width * height),
+            upscaled: Vec::new(),
+            marked: Vec::new(),
+            packed: Vec::new(),
+            zoom: 1,
+            view_x: 0,
+            view_y: 0,
+            view: Vec::new(),
+        }
+    }
+
+    pub fn channels(&self) -> usize {
+        self.channels
+    }
+
+    pub fn width(&self) -> usize {
+        self.width * self.scale
+    }
+
+    pub fn height(&self) -> usize {
+        self.height * self.scale
+    }
+
+    pub fn frame<'a>(&'a mut self, machine: &'a TuringMachine) -> &'a [u8] {
+        let (k, width) = (self.scale, self.width);
+        let (screen_width, screen_height) = (self.width(), self.height());
+        let mut symbols = Screen::symbols(k, width, self.height, &mut self.display, &mut self.upscaled, machine);
+        if self.head_marker || self.inspected {
+            self.marked.clear();
+            self.marked.extend_from_slice(symbols);
+            if self.head_marker {
+                Screen::mark_head(k, width, self.height, &mut self.marked, machine, 1, HEAD_MARKER);
+            }
+            if self.inspected {
+                let reach = INSPECTED_REACH;
+                Screen::mark_head(k, width, self.height, &mut self.marked, machine, reach, INSPECT_MARKER);
+            }
+            symbols = &self.marked;
+        }
+
+        let pixels = if self.channels == 1 {
+            symbols
+        } else {
+            let blank = vec![0; width];
+            self.packed.clear();
+            for (y, row) in symbols.chunks_exact(width * k).enumerate() {
+                let shade = machine.layers.first().map_or(&blank[..], |plane| &plane[width * (y / k)..][..width]);
+                for (x, &symbol) in row.iter().enumerate() {
+                    self.packed.push(symbol);
+                    self.packed.push(shade[x / k]);
+                }
+            }
+            &self.packed[..]
+        };
+        if self.hud.is_empty() && self.zoom == 1 && self.view_x == 0 && self.view_y == 0 {
+            return pixels;
+        }
+
+        // The view is sampled in window pixels, so every zoomed cell keeps its texture and
+        // marker, and wraps around the edges of the map.
+        let (zoom, channels) = (self.zoom, self.channels);
+        let (left, bottom) = (self.view_x * k, self.view_y * k);
+        self.view.clear();
+        for py in 0..screen_height {
+            let row = screen_width * ((bottom + py / zoom) % screen_height);
+            for px in 0..screen_width {
+                let source = channels * (row + (left + px / zoom) % screen_width);
+                self.view.extend_from_slice(&pixels[source..source + channels]);
+            }
+        }
+        hud::draw(&mut self.view, channels, screen_width, screen_height, &self.hud, self.hud_paper);
+        &self.view
+    }
+
+    pub fn zoom(&self) -> usize {
+        self.zoom
+    }
+
+    /// Doubles or halves the zoom, between 1x and `MAX_ZOOM`, keeping the cell in the middle of
+    /// the window where it is.
+    pub fn change_zoom(&mut self, zoom_in: bool) {
+        let zoom = if zoom_in {
+            (self.zoom * 2).min(MAX_ZOOM)
+        } else {
+            (self.zoom / 2).max(1)
+        };
+        let (center_x, center_y) = (self.width / self.zoom / 2, self.height / self.zoom / 2);
+        let (new_x, new_y) = (self.width / zoom / 2, self.height / zoom / 2);
+        self.pan((center_x as isize) - new_x as isize, (center_y as isize) - new_y as isize);
+        self.zoom = zoom;
+    }
+
+    /// Moves the view by `dx` cells along x and `dy` cells towards the top of the window,
+    /// wrapping around the edges of the map.
+    pub fn pan(&mut self, dx: isize, dy: isize) {
+        self.view_x = (self.view_x as isize + dx).rem_euclid(self.width as isize) as usize;
+        self.view_y = (self.view_y as isize + dy).rem_euclid(self.height as isize) as usize;
+    }
+
+    /// Cells visible across the window at the current zoom.
+    pub fn visible_width(&self) -> usize {
+        self.width / self.zoom
+    }
+
+    /// Back to the whole map at 1x.
+    pub fn reset_view(&mut self) {
+        self.zoom = 1;
+        self.view_x = 0;
+        self.view_y = 0;
+    }
+
+    /// The display cell under window pixel `x`, `y`, counted from the bottom left, with the
+    /// fraction of a cell kept. The result is not wrapped, so a drag across the edge of the map
+    /// stays continuous; reduce it modulo the map size to get a cell.
+    pub fn cell_at(&self, x: f64, y: f64) -> (f64, f64) {
+        let cell = (self.scale * self.zoom) as f64;
+        (self.view_x as f64 + x / cell, self.view_y as f64 + y / cell)
+    }
+
+    /// Sets the square ring of cells `reach` cells out from the head to `marker`, wrapping
+    /// around the edges. A line machine's live row is the last row of its display.
+    fn mark_head(
+        scale: usize,
+        width: usize,
+        height: usize,
+        pixels: &mut [u8],
+        machine: &TuringMachine,
+        reach: usize,
+        marker: u8,
+    ) {
+        let (x, y) = match machine.mode {
+            Mode::Line => (machine.xpos, height - 1),
+            Mode::Plane | Mode::Hex => (machine.xpos, machine.ypos),
+        };
+        for dy in 0..=2 * reach {
+            for dx in 0..=2 * reach {
+                if dx != 0 && dx != 2 * reach && dy != 0 && dy != 2 * reach {
+                    continue;
+                }
+                let cx = (x + reach * width + dx - reach) % width;
+                let cy = (y + reach * height + dy - reach) % height;
+                for sy in 0..scale {
+                    let row = width * scale * (cy * scale + sy);
+                    pixels[row + cx * scale..][..scale].fill(marker);
+                }
+            }
+        }
+    }
+
+    /// One byte per pixel: the symbols, upscaled when `scale` is above 1.
+    fn symbols<'a>(
+        scale: usize,
+        width: usize,
+        height: usize,
+        display: &'a mut Vec<u8>,
+        upscaled: &'a mut Vec<u8>,
+        machine: &'a TuringMachine,
+    ) -> &'a [u8] {
+        let map = machine.display(display);
+        if scale == 1 {
+            return map;
+        }
+
+        let k = scale;
+        let hash = machine.genome_hash();
+        // patterns[symbol * k * k + k * sy + sx] is the value of that sub-cell for the symbol.
+        let patterns: Vec<u8> = (0..machine.num_symbols as usize)
+            .flat_map(|symbol| {
+                (0..k * k).map(move |sub| {
+                    let darker = (hash >> ((symbol * k * k + sub) % 64)) & 1 == 1;
+                    if darker && symbol > 0 {
+                        symbol as u8 - 1
+                    } else {
+                        symbol as u8
+                    }
+                })
+            })
+            .collect();
+
+        upscaled.resize(width * height * k * k, 0);
+        for (y, row) in map.chunks_exact(width).enumerate() {
+            for sy in 0..k {
+                let out = &mut upscaled[width * k * (y * k + sy)..][..width * k];
+                for (block, &symbol) in out.chunks_exact_mut(k).zip(row) {
+                    let base = symbol as usize * k * k + k * sy;
+                    block.copy_from_slice(&patterns[base..base + k]);
+                }
+            }
+        }
+        upscaled
+    }
+}
@@ -0,0 +1,464 @@
+use image::{Rgb, RgbImage};
+
+use crate::palette::Palette;
+use crate::screen::{HEAD_MARKER, INSPECT_MARKER};
+use crate::Mode;
+
+/// Mirroring applied by the shader before the map is colored. The simulation itself is never
+/// touched, only what ends up on screen.
+#[derive(Clone, Copy, PartialEq)]
+pub enum Symmetry {
+    None,
+    Two,
+    Four,
+    Eight,
+}
+
+impl Symmetry {
+    pub fn next(self) -> Symmetry {
+        match self {
+            Symmetry::None => Symmetry::Two,
+            Symmetry::Two => Symmetry::Four,
+            Symmetry::Four => Symmetry::Eight,
+            Symmetry::Eight => Symmetry::None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Symmetry::None => "none",
+            Symmetry::Two => "2-fold",
+            Symmetry::Four => "4-fold",
+            Symmetry::Eight => "8-fold",
+        }
+    }
+
+    /// GLSL statements that fold `uv` into the fundamental region of this symmetry.
+    fn fold(self) -> &'static str {
+        match self {
+            Symmetry::None => "",
+            Symmetry::Two => "uv.x = 0.5 - abs(uv.x - 0.5);",
+            Symmetry::Four => "uv = 0.5 - abs(uv - 0.5);",
+            Symmetry::Eight => "uv = 0.5 - abs(uv - 0.5); if (uv.y > uv.x) uv = uv.yx;",
+        }
+    }
+
+    /// `fold` on the CPU.
+    fn fold_uv(self, [x, y]: [f32; 2]) -> [f32; 2] {
+        let (folded_x, folded_y) = (0.5 - (x - 0.5).abs(), 0.5 - (y - 0.5).abs());
+        match self {
+            Symmetry::None => [x, y],
+            Symmetry::Two => [folded_x, y],
+            Symmetry::Four => [folded_x, folded_y],
+            Symmetry::Eight if folded_y > folded_x => [folded_y, folded_x],
+            Symmetry::Eight => [folded_x, folded_y],
+        }
+    }
+}
+
+/// Post-processing look applied on top of the symbol colors.
+#[derive(Clone, Copy, PartialEq)]
+pub enum Style {
+    Plain,
+    /// Barrel distortion, scanlines and a little chromatic aberration.
+    Crt,
+    /// Ordered dithering down to a few levels per channel with a 4x4 Bayer matrix.
+    Dither,
+}
+
+impl Style {
+    pub fn next(self) -> Style {
+        match self {
+            Style::Plain => Style::Crt,
+            Style::Crt => Style::Dither,
+            Style::Dither => Style::Plain,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Style::Plain => "plain",
+            Style::Crt => "crt",
+            Style::Dither => "dither",
+        }
+    }
+}
+
+/// Everything that decides how the map is drawn. Changing any of it means rebuilding the
+/// post-process shader, since the tweakable constants are baked into its source. `image` draws
+/// the same picture on the CPU for screenshots and recordings.
+#[derive(Clone, Copy)]
+pub struct Shading {
+    pub symmetry: Symmetry,
+    pub style: Style,
+    pub palette: Palette,
+    /// Symbols of the machine on screen, which ramp palettes are spread over.
+    pub num_symbols: u16,
+    /// Strength of the CRT barrel distortion.
+    pub curvature: f32,
+    /// Levels per color channel the dither style quantizes to.
+    pub dither_levels: u32,
+    /// Topology of the map, which decides whether odd rows are offset.
+    mode: Mode,
+    /// Size of the map in cells, which the hex offset and the CRT effects are measured in.
+    width: usize,
+    height: usize,
+}
+
+impl Shading {
+    pub fn new(mode: Mode, width: usize, height: usize, num_symbols: u16) -> Shading {
+        Shading {
+            symmetry: Symmetry::None,
+            style: Style::Plain,
+            palette: Palette::Classic,
+            num_symbols,
+            curvature: 0.1,
+            dither_levels: 2,
+            mode,
+            width,
+            height,
+        }
+    }
+
+    /// Nudges the main constant of the active style up or down by one notch.
+    pub fn adjust(&mut self, up: bool) {
+        match self.style {
+            Style::Plain => {}
+            Style::Crt => {
+                let step = if up { 0.05 } else { -0.05 };
+                self.curvature = (self.curvature + step).clamp(0.0, 1.0);
+            }
+            Style::Dither => {
+                self.dither_levels = if up {
+                    (self.dither_levels + 1).min(16)
+                } else {
+                    (self.dither_levels - 1).max(2)
+                };
+            }
+        }
+    }
+
+    /// Builds the post-process shader. The uv is first distorted for the CRT look, folded for
+    /// the symmetry and, on hex maps, shifted half a cell on odd rows so the cells line up like a
+    /// honeycomb. The style then decides how the folded uv is colored.
+    pub fn shader(&self) -> String {
+        let barrel = match self.style {
+            Style::Crt => format!(
+                "vec2 centered = uv * 2.0 - 1.0;
+        centered *= 1.0 + {:.3} * dot(centered, centered);
+        uv = centered * 0.5 + 0.5;
+        if (uv.x < 0.0 || uv.x > 1.0 || uv.y < 0.0 || uv.y > 1.0) {{
+            r_frag_color = vec4(0.0, 0.0, 0.0, 1.0);
+            return;
+        }}",
+                self.curvature
+            ),
+            _ => String::new(),
+        };
+
+        let offset = if self.mode == Mode::Hex {
+            format!(
+                "if (mod(floor(uv.y * {}.0), 2.0) == 1.0) uv.x -= 0.5 / {}.0;",
+                self.height, self.width
+            )
+        } else {
+            String::new()
+        };
+
+        let color = match self.style {
+            Style::Plain => "color_symbol(r_frag_color, uv);".to_string(),
+            Style::Crt => format!(
+                "vec4 red;
+        vec4 green;
+        vec4 blue;
+        color_symbol(red, uv + vec2(1.5 / {width}.0, 0.0));
+        color_symbol(green, uv);
+        color_symbol(blue, uv - vec2(1.5 / {width}.0, 0.0));
+        float scanline = 0.5 + 0.5 * sin(uv.y * {height}.0 * {pi:.7});
+        r_frag_color = vec4(red.r, green.g, blue.b, 1.0);
+        r_frag_color.rgb *= mix(1.0, scanline, {depth:.2});",
+                width = self.width,
+                height = self.height,
+                pi = std::f32::consts::PI,
+                depth = SCANLINE_DEPTH
+            ),
+            Style::Dither => format!(
+                "const float bayer[16] = float[16]({bayer});
+        color_symbol(r_frag_color, uv);
+        int index = int(mod(gl_FragCoord.y, 4.0)) * 4 + int(mod(gl_FragCoord.x, 4.0));
+        float threshold = (bayer[index] + 0.5) / 16.0;
+        vec3 scaled = clamp(r_frag_color.rgb, 0.0, 1.0) * {levels:.1};
+        r_frag_color.rgb = (floor(scaled) + step(threshold, fract(scaled))) / {levels:.1};",
+                bayer = BAYER.iter().map(|b| format!("{:.1}", b)).collect::<Vec<_>>().join(", "),
+                levels = (self.dither_levels - 1) as f32
+            ),
+        };
+
+        format!(
+            "{}
+    void main_image( out vec4 r_frag_color, in vec2 uv )
+    {{
+        {}
+        {}
+        {}
+        {}
+    }}
+",
+            color_symbol(&self.palette.colors(self.num_symbols), self.num_symbols),
+            barrel,
+            self.symmetry.fold(),
+            offset,
+            color
+        )
+    }
+
+    /// Colors a window buffer on the CPU the way `shader` draws it, one pixel per buffer pixel,
+    /// so screenshots and recordings look like the screen. The buffer holds `channels` bytes per
+    /// pixel, the symbol and then layer 1, with row 0 at the bottom of the window.
+    pub fn image(&self, pixels: &[u8], channels: usize, width: usize, height: usize) -> RgbImage {
+        let colors = self.palette.colors(self.num_symbols);
+        // Nearest texel, repeating past the edges like the buffer texture.
+        let sample = |[u, v]: [f32; 2]| {
+            let x = ((u * width as f32).floor() as isize).rem_euclid(width as isize) as usize;
+            let y = ((v * height as f32).floor() as isize).rem_euclid(height as isize) as usize;
+            let texel = &pixels[channels * (width * y + x)..][..channels];
+            self.color_symbol(&colors, texel[0], texel.get(1).copied().unwrap_or(0))
+        };
+        RgbImage::from_fn(width as u32, height as u32, |x, y| {
+            let frag = [x as f32 + 0.5, (height - 1 - y as usize) as f32 + 0.5];
+            let uv = [frag[0] / width as f32, frag[1] / height as f32];
+            Rgb(self.main_image(frag, uv, sample).map(|c| (c.clamp(0.0, 1.0) * 255.0).round() as u8))
+        })
+    }
+
+    /// `main_image` from `shader` for the fragment at window pixel `frag`, which is at `uv`.
+    fn main_image(&self, frag: [f32; 2], mut uv: [f32; 2], sample: impl Fn([f32; 2]) -> [f32; 3]) -> [f32; 3] {
+        if self.style == Style::Crt {
+            let centered = [uv[0] * 2.0 - 1.0, uv[1] * 2.0 - 1.0];
+            let bulge = 1.0 + self.curvature * (centered[0] * centered[0] + centered[1] * centered[1]);
+            uv = [centered[0] * bulge * 0.5 + 0.5, centered[1] * bulge * 0.5 + 0.5];
+            if uv.iter().any(|c| !(0.0..=1.0).contains(c)) {
+                return [0.0; 3];
+            }
+        }
+        uv = self.symmetry.fold_uv(uv);
+        if self.mode == Mode::Hex && (uv[1] * self.height as f32).floor().rem_euclid(2.0) == 1.0 {
+            uv[0] -= 0.5 / self.width as f32;
+        }
+
+        match self.style {
+            Style::Plain => sample(uv),
+            Style::Crt => {
+                let shift = 1.5 / self.width as f32;
+                let red = sample([uv[0] + shift, uv[1]])[0];
+                let green = sample(uv)[1];
+                let blue = sample([uv[0] - shift, uv[1]])[2];
+                let scanline = 0.5 + 0.5 * (uv[1] * self.height as f32 * std::f32::consts::PI).sin();
+                let dim = 1.0 - SCANLINE_DEPTH + scanline * SCANLINE_DEPTH;
+                [red * dim, green * dim, blue * dim]
+            }
+            Style::Dither => {
+                let index = 4 * (frag[1] as usize % 4) + frag[0] as usize % 4;
+                let threshold = (BAYER[index] + 0.5) / 16.0;
+                let levels = (self.dither_levels - 1) as f32;
+                sample(uv).map(|c| {
+                    let scaled = c.clamp(0.0, 1.0) * levels;
+                    let up = if scaled.fract() >= threshold { 1.0 } else { 0.0 };
+                    (scaled.floor() + up) / levels
+                })
+            }
+        }
+    }
+
+    /// `color_symbol` from `shader` for a texel holding `symbol`, with `shade` on layer 1.
+    fn color_symbol(&self, colors: &[[u8; 3]], symbol: u8, shade: u8) -> [f32; 3] {
+        let color = match symbol {
+            HEAD_MARKER => [255; 3],
+            INSPECT_MARKER => INSPECT_COLOR,
+            _ => colors[(symbol as usize).min(colors.len() - 1)],
+        };
+        let darken = 1.0 - LAYER_SHADE * (shade as f32 / (self.num_symbols.max(2) as f32 - 1.0)).min(1.0);
+        color.map(|c| c as f32 / 255.0 * darken)
+    }
+}
+
+/// Share of the brightness the CRT scanlines take away at their darkest.
+const SCANLINE_DEPTH: f32 = 0.35;
+
+/// 4x4 Bayer matrix the dither style takes its thresholds from, row by row.
+const BAYER: [f32; 16] = [0.0, 8.0, 2.0, 10.0, 12.0, 4.0, 14.0, 6.0, 3.0, 11.0, 1.0, 9.0, 15.0, 7.0, 13.0, 5.0];
+
+/// Color of the ring around an inspected machine. The head marker is white, so this is a color
+/// none of the presets use.
+const INSPECT_COLOR: [u8; 3] = [255, 64, 192];
+
+/// How much the highest symbol on layer 1 darkens a color.
+const LAYER_SHADE: f32 = 0.6;
+
+/// GLSL for `color_symbol`, which looks up the color of the symbol at `uv` in `colors` and
+/// darkens it by the layer 1 symbol in the second channel, if the buffer has one.
+fn color_symbol(colors: &[[u8; 3]], num_symbols: u16) -> String {
+    let glsl = |[r, g, b]: [u8; 3]| {
+        format!("vec4({:.6}, {:.6}, {:.6}, 1.0)", r as f32 / 255.0, g as f32 / 255.0, b as f32 / 255.0)
+    };
+
+    let mut cases = String::new();
+    for (symbol, &color) in colors.iter().enumerate().take(colors.len() - 1) {
+        cases += &format!(
+            "
+        case {}:
+            r_frag_color = {};
+            break;",
+            symbol,
+            glsl(color)
+        );
+    }
+
+    format!(
+        "
+void color_symbol( out vec4 r_frag_color, in vec2 uv )
+{{
+    vec4 texel = texture(u_buffer, uv);
+    int symbol = int(texel.r * 255);
+    switch (symbol) {{{}
+        case {}:
+            r_frag_color = vec4(1.0);
+            break;
+        case {}:
+            r_frag_color = {};
+            break;
+        default:
+            r_frag_color = {};
+            break;
+    }}
+    r_frag_color.rgb *= 1.0 - {:.6} * min(texel.g * 255.0 / {:.1}, 1.0);
+}}
+",
+        cases,
+        HEAD_MARKER,
+        INSPECT_MARKER,
+        glsl(INSPECT_COLOR),
+        glsl(colors[colors.len() - 1]),
+        LAYER_SHADE,
+        num_symbols.max(2) as f32 - 1.0
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A buffer of `width` x `height` one-channel pixels counting through `num_symbols` symbols.
+    fn buffer(width: usize, height: usize, num_symbols: u8) -> Vec<u8> {
+        (0..width * height).map(|i| (i * 7 % num_symbols as usize) as u8).collect()
+    }
+
+    /// The color `color_symbol` gives each of `num_symbols` symbols, read back out of its GLSL:
+    /// the symbol's case if it has one and the default otherwise.
+    fn glsl_colors(glsl: &str, num_symbols: usize) -> Vec<[f32; 3]> {
+        let mut cases = Vec::new();
+        let mut default = None;
+        let mut label = None;
+        for line in glsl.lines().map(str::trim) {
+            if let Some(case) = line.strip_prefix("case ") {
+                label = Some(case.trim_end_matches(':').parse::<usize>().unwrap());
+            } else if line == "default:" {
+                label = None;
+            } else if let Some(vec4) = line.strip_prefix("r_frag_color = vec4(") {
+                let values: Vec<f32> = vec4.trim_end_matches(");").split(", ").map(|v| v.parse().unwrap()).collect();
+                if values.len() == 4 {
+                    let color = [values[0], values[1], values[2]];
+                    match label {
+                        Some(symbol) => cases.push((symbol, color)),
+                        None => default = Some(color),
+                    }
+                }
+            }
+        }
+        (0..num_symbols)
+            .map(|symbol| cases.iter().find(|&&(case, _)| case == symbol).map_or(default.unwrap(), |&(_, color)| color))
+            .collect()
+    }
+
+    #[test]
+    fn gpu_colors_match_the_palette() {
+        let mut index = 0;
+        while let Some(palette) = Palette::nth(index) {
+            for &num_symbols in &[2, 3, 7, 16, 64] {
+                let colors = palette.colors(num_symbols);
+                let glsl = glsl_colors(&color_symbol(&colors, num_symbols), num_symbols as usize);
+                let mut shading = Shading::new(Mode::Plane, 1, 1, num_symbols);
+                shading.palette = palette;
+                for (symbol, gpu) in glsl.iter().enumerate() {
+                    let cpu = colors[symbol.min(colors.len() - 1)];
+                    let exported = shading.color_symbol(&colors, symbol as u8, 0);
+                    for channel in 0..3 {
+                        let expected = cpu[channel] as f32 / 255.0;
+                        assert!(
+                            (gpu[channel] - expected).abs() <= 1.0 / 255.0
+                                && (exported[channel] - expected).abs() <= 1.0 / 255.0,
+                            "{} with {} symbols: symbol {} is {:?} on the GPU and {:?} on the CPU",
+                            palette.name(),
+                            num_symbols,
+                            symbol,
+                            gpu,
+                            cpu
+                        );
+                    }
+                }
+            }
+            index += 1;
+        }
+    }
+
+    #[test]
+    fn plain_exports_match_the_palette() {
+        let pixels = buffer(16, 9, 5);
+        let mut shading = Shading::new(Mode::Plane, 16, 9, 5);
+        for &palette in &[Palette::Classic, Palette::Viridis, Palette::Rainbow] {
+            shading.palette = palette;
+            assert!(shading.image(&pixels, 1, 16, 9) == palette.image(&pixels, 16, 9, 5));
+        }
+    }
+
+    #[test]
+    fn exports_carry_the_style() {
+        let pixels = vec![0; 32 * 32];
+        let mut shading = Shading::new(Mode::Plane, 32, 32, 2);
+        shading.palette = Palette::Viridis;
+
+        shading.style = Style::Crt;
+        shading.curvature = 0.5;
+        let crt = shading.image(&pixels, 1, 32, 32);
+        assert_eq!(crt.get_pixel(0, 0), &Rgb([0, 0, 0]), "the corners are bent out of the picture");
+        assert!(crt.get_pixel(16, 16) != crt.get_pixel(16, 17), "neighbouring rows fall on different scanlines");
+
+        shading.style = Style::Dither;
+        let dither = shading.image(&pixels, 1, 32, 32);
+        assert!(dither.pixels().flat_map(|pixel| pixel.0).all(|c| c == 0 || c == 255));
+        assert!(dither.pixels().any(|pixel| pixel != dither.get_pixel(0, 0)), "mid colors are dithered");
+    }
+
+    #[test]
+    fn exports_carry_the_symmetry() {
+        let pixels = buffer(12, 12, 7);
+        let mut shading = Shading::new(Mode::Plane, 12, 12, 7);
+        shading.symmetry = Symmetry::Eight;
+        let image = shading.image(&pixels, 1, 12, 12);
+        for y in 0..12 {
+            for x in 0..12 {
+                assert_eq!(image.get_pixel(x, y), image.get_pixel(11 - x, y));
+                assert_eq!(image.get_pixel(x, y), image.get_pixel(y, x));
+            }
+        }
+    }
+
+    #[test]
+    fn exports_are_shaded_by_layer_1() {
+        let mut shading = Shading::new(Mode::Plane, 2, 1, 3);
+        shading.palette = Palette::Contrast;
+        let image = shading.image(&[1, 0, 1, 2], 2, 2, 1);
+        assert_eq!(image.get_pixel(0, 0), &Rgb([255, 255, 255]));
+        assert_eq!(image.get_pixel(1, 0), &Rgb([102, 102, 102]));
+    }
+}
@@ -0,0 +1,34 @@
+use crate::{wrapping_move, TuringMachine};
+
+/// A 5x5 glyph derived from a genome hash. Fifteen bits fill the left three columns and the
+/// right two mirror them, which makes glyphs read as symbols rather than noise and lets
+/// different hashes come out visibly different.
+pub fn glyph(hash: u64) -> [[bool; 5]; 5] {
+    let mut glyph = [[false; 5]; 5];
+    for (row, cells) in glyph.iter_mut().enumerate() {
+        for col in 0..3 {
+            let bit = (hash >> (row * 3 + col)) & 1 == 1;
+            cells[col] = bit;
+            cells[4 - col] = bit;
+        }
+    }
+    glyph
+}
+
+/// Stamps the machine's glyph onto the map centered on its head, wrapping around the edges.
+/// Set cells get a nonzero symbol picked from the hash, so the glyph is always visible and
+/// never leaves the machine's symbol range.
+pub fn stamp(machine: &mut TuringMachine) {
+    let hash = machine.genome_hash();
+    let symbol = 1 + ((hash >> 15) % (machine.num_symbols as u64 - 1)) as u8;
+
+    for (dy, cells) in glyph(hash).iter().enumerate() {
+        for (dx, &set) in cells.iter().enumerate() {
+            if set {
+                let x = wrapping_move(machine.xpos, dx as isize - 2, machine.width);
+                let y = wrapping_move(machine.ypos, dy as isize - 2, machine.height);
+                machine.map[machine.width * y + x] = symbol;
+            }
+        }
+    }
+}
@@ -0,0 +1,406 @@
+use rand::rngs::SmallRng;
+use rand::SeedableRng;
+
+use crate::command::{Command, Setting};
+use crate::palette::Palette;
+use crate::rewind::Snapshot;
+use crate::save;
+use crate::{check_generation, size_fits};
+use crate::{GenerationParams, Mode, StartState, TableSymmetry, TuringMachine, ACTIONS, DEFAULT_HEIGHT, DEFAULT_WIDTH};
+
+/// Everything needed to set up a simulation.
+#[derive(Clone)]
+pub struct Recipe {
+    /// Machine string to run. A random machine is generated when this is `None`.
+    pub genome: Option<String>,
+    /// Size of random machines.
+    pub num_states: u16,
+    pub num_symbols: u16,
+    pub generation: GenerationParams,
+    pub mode: Mode,
+    pub width: usize,
+    pub height: usize,
+    pub steps_per_frame: u32,
+    /// Palette preset frames are colored in.
+    pub palette: Palette,
+    /// Seed for random machines, so the same recipe always builds the same machine. Without
+    /// one the machine comes from entropy.
+    pub seed: Option<u64>,
+}
+
+impl Default for Recipe {
+    fn default() -> Recipe {
+        Recipe {
+            genome: None,
+            num_states: 12,
+            num_symbols: 7,
+            generation: GenerationParams {
+                sparse_bias: 0.0,
+                self_modify: false,
+                symmetry: TableSymmetry::None,
+                start: StartState::Fixed(0),
+                layers: 1,
+                sensing: false,
+            },
+            mode: Mode::Plane,
+            width: DEFAULT_WIDTH,
+            height: DEFAULT_HEIGHT,
+            steps_per_frame: 50_000,
+            palette: Palette::Classic,
+            seed: None,
+        }
+    }
+}
+
+/// Counters describing a running simulation.
+pub struct Stats {
+    pub iteration: u32,
+    pub self_modifications: u32,
+    /// Actions executed since the machine was created or reset, indexed like `ACTIONS`.
+    pub action_counts: [u32; ACTIONS.len()],
+    /// Cells holding each symbol, indexed by symbol.
+    pub symbols: Vec<u64>,
+}
+
+impl Stats {
+    /// Column names for `csv_row`. The histogram is one column of space separated counts, since
+    /// the number of symbols changes with the machine.
+    pub const CSV_HEADER: &'static str = "iteration,self_modifications,num_symbols,symbols";
+
+    /// Takes the counters from `machine` and counts its map, which is a single pass over it.
+    pub fn of(machine: &TuringMachine) -> Stats {
+        let mut symbols = vec![0u64; machine.num_symbols as usize];
+        let last = symbols.len() - 1;
+        for &cell in &machine.map {
+            symbols[(cell as usize).min(last)] += 1;
+        }
+
+        Stats {
+            iteration: machine.itr_count,
+            self_modifications: machine.self_modifications,
+            action_counts: machine.action_counts,
+            symbols,
+        }
+    }
+
+    fn histogram(&self) -> String {
+        let counts: Vec<String> = self.symbols.iter().map(|count| count.to_string()).collect();
+        counts.join(" ")
+    }
+
+    /// Everything on one line, for the log.
+    pub fn line(&self) -> String {
+        format!(
+            "stats iteration {} self-modifications {} symbols {}",
+            self.iteration,
+            self.self_modifications,
+            self.histogram()
+        )
+    }
+
+    /// One line per value, with the histogram as a share of the map per symbol.
+    pub fn pretty(&self) -> String {
+        let cells: u64 = self.symbols.iter().sum();
+        let mut out = format!(
+            "stats\n  iteration          {}\n  self-modifications {}\n",
+            self.iteration, self.self_modifications
+        );
+        for (symbol, &count) in self.symbols.iter().enumerate() {
+            out += &format!(
+                "  symbol {:<11} {} ({:.1}%)\n",
+                symbol,
+                count,
+                100.0 * count as f64 / cells as f64
+            );
+        }
+        out
+    }
+
+    pub fn csv_row(&self) -> String {
+        format!(
+            "{},{},{},{}",
+            self.iteration,
+            self.self_modifications,
+            self.symbols.len(),
+            self.histogram()
+        )
+    }
+}
+
+/// A machine and its map behind a small API for driving the simulation from other programs.
+/// Frames come out as RGBA bytes in the same colors the window uses, rows top to bottom.
+pub struct Simulation {
+    machine: TuringMachine,
+    /// Random numbers for the run, picked up from generating the machine.
+    rng: SmallRng,
+    generation: GenerationParams,
+    steps_per_frame: u32,
+    palette: Palette,
+    playing: bool,
+    display: Vec<u8>,
+    rgba: Vec<u8>,
+}
+
+impl Simulation {
+    /// Builds the machine the recipe asks for. A recipe `TuringMachine::new` or `from_string`
+    /// would reject comes back as an error rather than a panic.
+    pub fn new(recipe: Recipe) -> Result<Simulation, String> {
+        if !size_fits(recipe.width, recipe.height) {
+            return Err("the map must be at least 2x2".to_string());
+        }
+        let mut rng = match recipe.seed {
+            Some(seed) => SmallRng::seed_from_u64(seed),
+            None => SmallRng::from_entropy(),
+        };
+        let machine = match &recipe.genome {
+            Some(genome) => {
+                TuringMachine::from_string(genome, recipe.width, recipe.height).map_err(|error| error.to_string())?
+            }
+            None => {
+                check_generation(recipe.num_states, recipe.num_symbols, &recipe.generation, recipe.mode)?;
+                TuringMachine::new(
+                    recipe.num_states,
+                    recipe.num_symbols,
+                    recipe.generation,
+                    recipe.mode,
+                    recipe.width,
+                    recipe.height,
+                    &mut rng,
+                )
+            }
+        };
+
+        Ok(Simulation {
+            machine,
+            rng,
+            generation: recipe.generation,
+            steps_per_frame: recipe.steps_per_frame,
+            palette: recipe.palette,
+            playing: true,
+            display: Vec::new(),
+            rgba: Vec::new(),
+        })
+    }
+
+    pub fn machine(&self) -> &TuringMachine {
+        &self.machine
+    }
+
+    pub fn step(&mut self, num_iters: u32) {
+        self.machine.step(num_iters);
+    }
+
+    /// Runs one frame's worth of steps unless the simulation is paused.
+    pub fn advance(&mut self) {
+        if self.playing {
+            self.step(self.steps_per_frame);
+        }
+    }
+
+    pub fn palette(&self) -> Palette {
+        self.palette
+    }
+
+    pub fn set_palette(&mut self, palette: Palette) {
+        self.palette = palette;
+    }
+
+    pub fn frame_rgba(&mut self) -> &[u8] {
+        let (width, height) = (self.machine.width, self.machine.height);
+        let buffer = self.machine.display(&mut self.display);
+
+        let colors = self.palette.colors(self.machine.num_symbols);
+        self.rgba.clear();
+        for row in buffer.chunks_exact(width).rev() {
+            for &symbol in row {
+                self.rgba.extend_from_slice(&colors[(symbol as usize).min(colors.len() - 1)]);
+                self.rgba.push(255);
+            }
+        }
+        debug_assert_eq!(self.rgba.len(), width * height * 4);
+        &self.rgba
+    }
+
+    pub fn stats(&self) -> Stats {
+        Stats::of(&self.machine)
+    }
+
+    /// Applies a REPL command. `spawn` replaces the machine with one of the same map size and
+    /// topology, and `save` writes it to a save file.
+    pub fn apply(&mut self, command: Command) -> Result<(), String> {
+        match command {
+            Command::Pause => self.playing = false,
+            Command::Play => self.playing = true,
+            Command::Step(count) => self.step(count),
+            Command::Reset => self.machine.reset(),
+            Command::Set(Setting::StepsPerFrame(steps)) => self.steps_per_frame = steps,
+            Command::Set(Setting::SparseBias(bias)) => self.generation.sparse_bias = bias,
+            Command::Spawn(genome) => {
+                let spawned = TuringMachine::from_string(&genome, self.machine.width, self.machine.height)
+                    .map_err(|error| error.to_string())?;
+                if spawned.mode != self.machine.mode {
+                    return Err("the machine is for a different topology".to_string());
+                }
+                self.machine = spawned;
+            }
+            Command::Save(path) => {
+                save::write(&self.machine, &path).map_err(|error| format!("could not save {}: {}", path.display(), error))?
+            }
+        }
+        Ok(())
+    }
+
+    pub fn snapshot(&self) -> Snapshot {
+        Snapshot::take(&self.machine, &self.rng, None)
+    }
+
+    /// Goes back to a snapshot, along with the machine it was taken from if a spawn has
+    /// replaced that machine since.
+    pub fn restore(&mut self, snapshot: &Snapshot) {
+        snapshot.restore(&mut self.machine, &mut self.rng, None);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn recipe(seed: u64) -> Recipe {
+        Recipe {
+            width: 64,
+            height: 48,
+            seed: Some(seed),
+            ..Recipe::default()
+        }
+    }
+
+    #[test]
+    fn same_seed_gives_the_same_run() {
+        let mut first = Simulation::new(recipe(264)).unwrap();
+        let mut second = Simulation::new(recipe(264)).unwrap();
+        first.step(10_000);
+        second.step(10_000);
+        assert_eq!(first.machine().to_string(), second.machine().to_string());
+        assert!(first.machine().map == second.machine().map);
+
+        let mut other = Simulation::new(recipe(265)).unwrap();
+        other.step(10_000);
+        assert_ne!(first.machine().to_string(), other.machine().to_string());
+    }
+
+    #[test]
+    fn frames_use_the_recipes_palette() {
+        let mut simulation = Simulation::new(Recipe {
+            palette: Palette::Contrast,
+            ..recipe(254)
+        })
+        .unwrap();
+        assert!(simulation.palette() == Palette::Contrast);
+        // A fresh map is all symbol 0, which is black in the contrast palette.
+        assert!(simulation.frame_rgba().chunks_exact(4).all(|pixel| pixel == [0, 0, 0, 255]));
+
+        simulation.set_palette(Palette::Classic);
+        assert!(simulation.frame_rgba().chunks_exact(4).all(|pixel| pixel == [255, 0, 0, 255]));
+    }
+
+    #[test]
+    fn rejects_bad_recipes() {
+        let bad = |recipe: Recipe| Simulation::new(recipe).err().expect("the recipe should be rejected");
+        assert_eq!(bad(Recipe { width: 1, ..recipe(256) }), "the map must be at least 2x2");
+        assert_eq!(
+            bad(Recipe { num_states: 0, ..recipe(256) }),
+            "must have between 1 and 255 states"
+        );
+        let mut symmetric = recipe(256);
+        symmetric.num_states = 3;
+        symmetric.num_symbols = 3;
+        symmetric.generation.symmetry = TableSymmetry::H;
+        assert_eq!(bad(symmetric), "symmetric tables need an even number of entries");
+        let mut layered = recipe(256);
+        layered.mode = Mode::Hex;
+        layered.generation.layers = 2;
+        assert_eq!(bad(layered), "only square plane machines can have more than 1 layer");
+        let mut sensing = recipe(256);
+        sensing.mode = Mode::Line;
+        sensing.generation.sensing = true;
+        assert_eq!(bad(sensing), "only square plane machines can sense their neighbours");
+    }
+
+    #[test]
+    fn restore_brings_back_the_table_and_counters() {
+        // Symbol 0 is overwritten with 1 and rewrites an entry, symbol 1 with 0 and moves right.
+        let self_modifying = Recipe {
+            genome: Some("v3:1,2,0,1,8,0,0,1".to_string()),
+            ..recipe(256)
+        };
+        let mut simulation = Simulation::new(self_modifying).unwrap();
+        simulation.step(1);
+        let snapshot = simulation.snapshot();
+        let genome = simulation.machine().to_string();
+        let before = simulation.stats();
+
+        simulation.step(10);
+        assert_ne!(simulation.machine().to_string(), genome);
+        simulation.restore(&snapshot);
+
+        let after = simulation.stats();
+        assert_eq!(simulation.machine().to_string(), genome);
+        assert_eq!(after.iteration, before.iteration);
+        assert_eq!(after.self_modifications, before.self_modifications);
+        assert_eq!(after.action_counts, before.action_counts);
+        assert_eq!(after.symbols, before.symbols);
+    }
+
+    #[test]
+    fn restore_goes_back_over_a_spawn() {
+        let mut simulation = Simulation::new(recipe(256)).unwrap();
+        simulation.step(5_000);
+        let snapshot = simulation.snapshot();
+        let genome = simulation.machine().to_string();
+        let map = simulation.machine().map.clone();
+        let mut unspawned = Simulation::new(recipe(256)).unwrap();
+        unspawned.step(5_000);
+
+        // Two layers, three symbols and a single state: nothing like the recipe's table.
+        let spawned = "v3:layers2:1,3,0,1,0,1,0,0,2,2,0,1,0,0,0,0,0";
+        simulation.apply(Command::Spawn(spawned.to_string())).unwrap();
+        simulation.step(1_000);
+        assert_eq!(simulation.machine().to_string(), spawned);
+        simulation.restore(&snapshot);
+
+        assert_eq!(simulation.machine().to_string(), genome);
+        assert!(simulation.machine().map == map);
+        assert!(simulation.machine().layers.is_empty());
+        simulation.step(5_000);
+        unspawned.step(5_000);
+        assert!(simulation.machine().map == unspawned.machine().map);
+        assert_eq!(simulation.stats().iteration, 10_000);
+    }
+
+    #[test]
+    fn save_command_writes_a_loadable_file() {
+        let path = std::env::temp_dir().join(format!("art-simulation-save-{}.save", std::process::id()));
+        let mut simulation = Simulation::new(recipe(219)).unwrap();
+        simulation.step(2_000);
+        simulation.apply(Command::Save(path.clone())).unwrap();
+        let loaded = save::read(&path, 64, 48).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert!(loaded.map == simulation.machine().map);
+        assert_eq!(loaded.to_string(), simulation.machine().to_string());
+
+        let missing = std::env::temp_dir().join("art-no-such-dir").join("world.save");
+        let error = simulation.apply(Command::Save(missing)).err().unwrap();
+        assert!(error.starts_with("could not save "), "{}", error);
+    }
+
+    #[test]
+    fn spawn_keeps_the_topology() {
+        let mut simulation = Simulation::new(recipe(219)).unwrap();
+        assert_eq!(
+            simulation.apply(Command::Spawn("1d:1,2,0,1,0,0,0,1".to_string())).err().unwrap(),
+            "the machine is for a different topology"
+        );
+        simulation.apply(Command::Spawn("v3:1,2,0,1,0,0,0,1".to_string())).unwrap();
+        assert_eq!(simulation.machine().to_string(), "v3:1,2,0,1,0,0,0,1");
+    }
+}
@@ -0,0 +1,47 @@
+/// Adjusts how many steps are simulated per frame so the window holds a target frame rate.
+/// Frame times are averaged over roughly a second before each adjustment, so a single slow
+/// frame doesn't make the speed jump around.
+pub struct SpeedController {
+    target_fps: f64,
+    min_steps: u32,
+    max_steps: u32,
+    frames: u32,
+    elapsed: f64,
+}
+
+impl SpeedController {
+    pub fn new(target_fps: f64, min_steps: u32, max_steps: u32) -> SpeedController {
+        assert!(target_fps > 0.0, "target fps must be positive");
+        assert!(min_steps >= 1, "min steps must be at least 1");
+        assert!(min_steps <= max_steps, "min steps must not exceed max steps");
+
+        SpeedController {
+            target_fps,
+            min_steps,
+            max_steps,
+            frames: 0,
+            elapsed: 0.0,
+        }
+    }
+
+    /// Records one frame that took `seconds` and returns the steps to run per frame from now
+    /// on. The step count is scaled by how far the measured rate is from the target. Zero steps
+    /// means stepping was turned off on purpose, so it is left alone.
+    pub fn update(&mut self, steps: u32, seconds: f64) -> u32 {
+        if steps == 0 {
+            return 0;
+        }
+        self.frames += 1;
+        self.elapsed += seconds;
+        if self.elapsed < 1.0 {
+            return steps;
+        }
+
+        let fps = self.frames as f64 / self.elapsed;
+        self.frames = 0;
+        self.elapsed = 0.0;
+
+        let scaled = steps as f64 * fps / self.target_fps;
+        (scaled as u32).clamp(self.min_steps, self.max_steps)
+    }
+}
@@ -0,0 +1,95 @@
+use crate::{wrapping_move, TuringMachine};
+
+/// Binomial rows used to build the kernels; the kernel weight of a cell is the product of its
+/// row and column entries.
+const ROW_3: [u32; 3] = [1, 2, 1];
+const ROW_5: [u32; 5] = [1, 4, 6, 4, 1];
+
+/// Airbrush-style writes. After the head writes a cell, the cells around it are blended toward
+/// the written symbol by `strength` scaled by their kernel weight relative to the center. The
+/// center cell itself keeps exactly the written symbol, so the table lookups stay well defined.
+pub struct Splat {
+    row: &'static [u32],
+    strength: f64,
+}
+
+impl Splat {
+    pub fn new(size: usize, strength: f64) -> Splat {
+        let row: &'static [u32] = match size {
+            3 => &ROW_3,
+            5 => &ROW_5,
+            _ => panic!("splat kernels are 3 or 5 cells wide"),
+        };
+        assert!((0.0..=1.0).contains(&strength), "splat strength must be between 0 and 1");
+
+        Splat { row, strength }
+    }
+
+    /// Blends the neighbourhood of the cell at `x`, `y` toward that cell's symbol, wrapping
+    /// around the edges of the map.
+    pub fn apply(&self, machine: &mut TuringMachine, x: usize, y: usize) {
+        let (width, height) = (machine.width, machine.height);
+        let value = machine.map[width * y + x] as f64;
+        let radius = self.row.len() / 2;
+        let center = (self.row[radius] * self.row[radius]) as f64;
+
+        for (dy, &row_weight) in self.row.iter().enumerate() {
+            for (dx, &col_weight) in self.row.iter().enumerate() {
+                if dx == radius && dy == radius {
+                    continue;
+                }
+                let cx = wrapping_move(x, dx as isize - radius as isize, width);
+                let cy = wrapping_move(y, dy as isize - radius as isize, height);
+                let cell = &mut machine.map[width * cy + cx];
+
+                let amount = self.strength * (row_weight * col_weight) as f64 / center;
+                let old = *cell as f64;
+                *cell = (old + (value - old) * amount).round() as u8;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A machine that never runs; only its map is splatted.
+    fn blank(width: usize, height: usize) -> TuringMachine {
+        TuringMachine::from_string("v3:1,8,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0", width, height).unwrap()
+    }
+
+    #[test]
+    fn blends_neighbours_and_wraps_at_the_corner() {
+        let mut machine = blank(6, 5);
+        machine.map[0] = 4;
+        Splat::new(3, 1.0).apply(&mut machine, 0, 0);
+
+        let cell = |x: usize, y: usize| machine.map[6 * y + x];
+        // The center keeps the written symbol exactly.
+        assert_eq!(cell(0, 0), 4);
+        // Edge neighbours weigh half the center, corner neighbours a quarter.
+        for &(x, y) in &[(1, 0), (5, 0), (0, 1), (0, 4)] {
+            assert_eq!(cell(x, y), 2, "{:?}", (x, y));
+        }
+        for &(x, y) in &[(1, 1), (5, 1), (1, 4), (5, 4)] {
+            assert_eq!(cell(x, y), 1, "{:?}", (x, y));
+        }
+        assert_eq!(machine.map.iter().filter(|&&cell| cell != 0).count(), 9);
+    }
+
+    #[test]
+    fn strength_scales_the_blend() {
+        let mut machine = blank(8, 8);
+        machine.map.fill(6);
+        machine.map[8 * 4 + 4] = 2;
+        Splat::new(5, 0.5).apply(&mut machine, 4, 4);
+
+        // The cell next to the center weighs 4 * 6 / 36 of it, so at half strength it moves a
+        // third of the way.
+        assert_eq!(machine.map[8 * 4 + 5], 5);
+        assert_eq!(machine.map[8 * 4 + 4], 2);
+        // The far corner weighs 1 / 36 and rounds back to where it was.
+        assert_eq!(machine.map[8 * 2 + 2], 6);
+    }
+}
@@ -0,0 +1,110 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+use crate::{Action, TuringMachine};
+
+/// The head of a machine right before a step.
+pub struct Before {
+    x: usize,
+    y: usize,
+    state: u8,
+    read: u8,
+    /// Layer the step writes its symbol to.
+    write_layer: u8,
+}
+
+impl Before {
+    pub fn capture(machine: &TuringMachine) -> Before {
+        let read = machine.plane(machine.layer)[machine.width * machine.ypos + machine.xpos];
+        Before {
+            x: machine.xpos,
+            y: machine.ypos,
+            state: machine.state,
+            read,
+            write_layer: machine.table[machine.table_index(read)].write_layer,
+        }
+    }
+}
+
+/// Writes one CSV row per simulation step of a traced machine. Rows go through a buffered
+/// writer so tracing doesn't turn every step into a syscall, and tracing stops by itself once
+/// `max_rows` rows have been written.
+pub struct Trace {
+    writer: BufWriter<File>,
+    rows: u64,
+    max_rows: u64,
+}
+
+impl Trace {
+    pub fn create(path: &Path, max_rows: u64) -> io::Result<Trace> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        writeln!(writer, "iteration,x,y,state,symbol_read,symbol_written,action")?;
+
+        Ok(Trace {
+            writer,
+            rows: 0,
+            max_rows,
+        })
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.rows >= self.max_rows
+    }
+
+    /// Writes the row for one step, given what the head saw before it, the action `step_once`
+    /// says it carried out and the machine after it.
+    pub fn record(&mut self, before: &Before, action: Action, machine: &TuringMachine) -> io::Result<()> {
+        writeln!(
+            self.writer,
+            "{},{},{},{},{},{},{}",
+            machine.itr_count.wrapping_sub(1),
+            before.x,
+            before.y,
+            before.state,
+            before.read,
+            machine.plane(before.write_layer)[machine.width * before.y + before.x],
+            action.name()
+        )?;
+        self.rows += 1;
+        Ok(())
+    }
+
+    /// Records why tracing ended as a final row, with only the iteration and the reason in the
+    /// action column filled in, and flushes the file.
+    pub fn finish(mut self, machine: &TuringMachine, reason: &str) -> io::Result<()> {
+        writeln!(self.writer, "{},,,,,,{}", machine.itr_count, reason)?;
+        self.writer.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::fs;
+
+    #[test]
+    fn records_the_action_that_was_carried_out() {
+        let path = std::env::temp_dir().join(format!("art-trace-test-{}.csv", std::process::id()));
+        let mut trace = Trace::create(&path, 10).unwrap();
+        // A version 1 string: its Left moves are carried out as Right.
+        let mut machine = TuringMachine::from_string("1,2,0,1,0,0,0,0", 4, 4).unwrap();
+        machine.xpos = 1;
+        for _ in 0..2 {
+            let before = Before::capture(&machine);
+            let action = machine.step_once();
+            trace.record(&before, action, &machine).unwrap();
+        }
+        machine.pull = Some(Action::Down);
+        let before = Before::capture(&machine);
+        let action = machine.step_once();
+        trace.record(&before, action, &machine).unwrap();
+        trace.finish(&machine, "done").unwrap();
+
+        let rows = fs::read_to_string(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        let rows: Vec<&str> = rows.lines().skip(1).collect();
+        assert_eq!(rows, ["0,1,0,0,0,1,Right", "1,2,0,0,0,1,Right", "2,3,0,0,0,1,Down", "3,,,,,,done"]);
+    }
+}